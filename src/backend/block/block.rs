@@ -2,84 +2,213 @@ use core::fmt::Debug;
 use std::fmt;
 use std::fmt::Formatter;
 
-use chrono::Utc;
 use crypto::{digest::Digest, sha2::*};
 
-use crate::backend::{functions::*, types::Hash, Hashable, Transaction, Transactions};
+use crate::backend::{
+    enums::DecodeError,
+    functions::*,
+    params::{ChainParams, MAINNET_DIFFICULTY},
+    traits::clock::{Clock, SystemClock},
+    traits::encodable::{put_opt_str, put_str, ByteReader},
+    types::Hash,
+    BlockHeader, BlockTemplate, Encodable, Hashable, Transaction, Transactions, ValueStore,
+};
 
+use super::header::header_bytes;
+
+#[derive(Clone)]
 pub struct Block {
     index: u64,
     timestamp: u64,
-    prev: Option<Sha256>,
+    prev: Option<Hash>,
     nonce: u64,
     difficulty: u128,
     transactions: Transactions,
-    hash: Sha256,
+    tx_root: Hash,
+    hash: Hash,
     broadcaster_addr: Hash,
+    state_root: Option<Hash>,
 }
 
 impl Block {
     pub fn gen_genesis() -> Block {
-        let mut initial_tx = Transactions::empty();
-        const INITIAL_BALANCE: u64 = 5000;
+        Block::genesis(&ChainParams::mainnet())
+    }
 
-        let mut init_wallet = Sha256::new();
-        init_wallet.input_str("Genesis Block");
-        let result_hash = init_wallet.result_str();
+    pub fn genesis(params: &ChainParams) -> Block {
+        Block::genesis_at(params, &SystemClock)
+    }
 
-        let initial_output = Transaction::new("".to_string(), result_hash.clone(), INITIAL_BALANCE);
+    // genesis block stamped with the time of `clock`.
+    pub fn genesis_at(params: &ChainParams, clock: &dyn Clock) -> Block {
+        let mut initial_tx = Transactions::empty();
+        let genesis_address = params.genesis_address();
+
+        // the coinbase input names the network, so networks never share a genesis hash.
+        let initial_output = Transaction::new(
+            params.network_tag(),
+            genesis_address.clone(),
+            params.genesis_balance(),
+        );
         initial_tx.values_mut().push(initial_output);
 
-        Block::mine(0, initial_tx, result_hash)
+        let mut block = Block::new(
+            0,
+            None,
+            initial_tx,
+            genesis_address,
+            params.difficulty(),
+            clock.now(),
+        );
+        block.state_root = block.genesis_state_root();
+        block._mine();
+
+        block
     }
 
     pub fn mine(block_idx: u64, transactions: Transactions, broadcaster_addr: Hash) -> Block {
-        let mut block = Block::new(block_idx, None, transactions, broadcaster_addr);
+        Block::mine_with(
+            block_idx,
+            transactions,
+            broadcaster_addr,
+            MAINNET_DIFFICULTY,
+        )
+    }
 
-        let mut arr: [u8; 32] = hash_array();
+    pub fn mine_with(
+        block_idx: u64,
+        transactions: Transactions,
+        broadcaster_addr: Hash,
+        difficulty: u128,
+    ) -> Block {
+        Block::mine_with_at(
+            block_idx,
+            transactions,
+            broadcaster_addr,
+            difficulty,
+            &SystemClock,
+        )
+    }
 
-        for nonce_target in 0..u64::MAX {
-            block.nonce = nonce_target;
+    // like `mine_with`, stamped with the time of `clock`.
+    pub fn mine_with_at(
+        block_idx: u64,
+        transactions: Transactions,
+        broadcaster_addr: Hash,
+        difficulty: u128,
+        clock: &dyn Clock,
+    ) -> Block {
+        let mut block = Block::new(
+            block_idx,
+            None,
+            transactions,
+            broadcaster_addr,
+            difficulty,
+            clock.now(),
+        );
+        block._mine();
 
-            let mut hash_result = block.hash();
-            hash_result.result(&mut arr);
+        block
+    }
+
+    // genesis block with a fixed timestamp, so the same allocations always give the same hash.
+    pub fn mine_genesis(
+        transactions: Transactions,
+        broadcaster_addr: Hash,
+        difficulty: u128,
+        timestamp: u64,
+    ) -> Block {
+        let mut block = Block::new(
+            0,
+            None,
+            transactions,
+            broadcaster_addr,
+            difficulty,
+            timestamp,
+        );
+        block.state_root = block.genesis_state_root();
+        block._mine();
 
-            let is_pass = check_difficulty(&arr, &block.difficulty);
+        block
+    }
 
-            if is_pass {
-                block.hash = block.hash();
-                println!(
-                    "mine attempt success. nonce : {}\n hash : {}",
-                    nonce_target,
-                    block.hash.result_str()
-                );
-                break;
-            }
+    // mine a block on top of `parent`. the parent hash is committed to by the block hash,
+    // so the block can be placed on any branch, not only the current tip.
+    pub fn mine_on(
+        parent: &BlockHeader,
+        transactions: Transactions,
+        broadcaster_addr: Hash,
+    ) -> Block {
+        Block::mine_on_at(parent, transactions, broadcaster_addr, &SystemClock)
+    }
 
-            continue;
-        }
+    // like `mine_on`, stamped with the time of `clock`.
+    // the same clock readings always give the same block.
+    pub fn mine_on_at(
+        parent: &BlockHeader,
+        transactions: Transactions,
+        broadcaster_addr: Hash,
+        clock: &dyn Clock,
+    ) -> Block {
+        let mut block = Block::new(
+            parent.index() + 1,
+            Some(parent.current_hash_str()),
+            transactions,
+            broadcaster_addr,
+            parent.difficulty(),
+            clock.now(),
+        );
+        block._mine();
+
+        block
+    }
+
+    // mine the block a template describes.
+    pub fn mine_template(template: &BlockTemplate) -> Block {
+        let mut block = Block::new(
+            template.index(),
+            Some(template.parent().current_hash_str()),
+            template.transactions().clone(),
+            template.coinbase().clone(),
+            template.difficulty(),
+            template.timestamp(),
+        );
+        block.state_root = Some(template.state_root().clone());
+        block._mine();
 
         block
     }
 
-    fn new(
+    pub(crate) fn new(
         index: u64,
-        prev_block: Option<Sha256>,
+        prev_block: Option<Hash>,
         transaction: Transactions,
         broadcaster_addr: Hash,
+        difficulty: u128,
+        timestamp: u64,
     ) -> Block {
         Block {
             index,
-            timestamp: Utc::now().timestamp_nanos() as u64,
+            timestamp,
             prev: prev_block,
             nonce: 0,
-            difficulty: 0x000ffffffffffffffffffffffffffff,
+            difficulty,
+            tx_root: transaction.root(),
             transactions: transaction,
-            hash: Sha256::new(),
+            hash: String::new(),
             broadcaster_addr,
+            state_root: None,
         }
     }
 
+    // the state after a genesis block only depends on its allocations, so it is known up front.
+    fn genesis_state_root(&self) -> Option<Hash> {
+        let mut values = ValueStore::new();
+        values.insert_genesis_balance(self).ok()?;
+
+        Some(values.commitment())
+    }
+
     fn _mine(&mut self) {
         let mut arr: [u8; 32] = hash_array();
 
@@ -92,11 +221,10 @@ impl Block {
             let is_pass = check_difficulty(&arr, &self.difficulty);
 
             if is_pass {
-                self.hash = self.hash();
+                self.hash = self.hash().result_str();
                 println!(
                     "mine attempt success. nonce : {}\n hash : {}",
-                    nonce_target,
-                    self.hash.result_str()
+                    nonce_target, self.hash
                 );
                 return;
             }
@@ -117,7 +245,11 @@ impl Block {
         self.difficulty
     }
 
-    pub fn current_hash(&self) -> &Sha256 {
+    pub fn tx_root(&self) -> &Hash {
+        &self.tx_root
+    }
+
+    pub fn current_hash(&self) -> &Hash {
         &self.hash
     }
 
@@ -130,40 +262,64 @@ impl Block {
     }
 
     pub fn current_hash_str(&self) -> Hash {
-        self.hash.clone().result_str()
+        self.hash.clone()
+    }
+
+    pub fn prev(&self) -> Option<&Hash> {
+        self.prev.as_ref()
     }
 
-    pub fn set_prev_block(&mut self, hash: Sha256) {
+    pub fn set_prev_block(&mut self, hash: Hash) {
         self.prev = Some(hash);
     }
 
-    pub fn current_hash_bytes(&mut self) -> [u8; 32] {
-        let mut arr: [u8; 32] = hash_array();
+    pub fn state_root(&self) -> Option<&Hash> {
+        self.state_root.as_ref()
+    }
 
-        self.hash.result(&mut arr);
+    // commit to the state after the block. the root is part of the hash, so the block is
+    // mined again. `BlockChain::seal_block` works it out.
+    pub fn with_state_root(mut self, root: Hash) -> Self {
+        self.state_root = Some(root);
+        self._mine();
+        self
+    }
 
-        arr
+    // serialized size in bytes.
+    pub fn size(&self) -> usize {
+        self.encode().len()
+    }
+
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader::new(
+            self.index,
+            self.timestamp,
+            self.prev.clone(),
+            self.nonce,
+            self.difficulty,
+            self.tx_root.clone(),
+            self.hash.clone(),
+            self.broadcaster_addr.clone(),
+            self.state_root.clone(),
+        )
+    }
+
+    pub fn current_hash_bytes(&self) -> [u8; 32] {
+        hash_str_bytes(&self.hash)
     }
 
     pub fn prev_hash_str(&self) -> Hash {
         match &self.prev {
-            Some(prev_unwrapped) => prev_unwrapped.clone().result_str(),
+            Some(prev_unwrapped) => prev_unwrapped.clone(),
             None => "".to_string(),
         }
     }
 
     pub fn prev_hash_bytes(&self) -> [u8; 32] {
-        let mut arr: [u8; 32] = hash_array();
-
-        arr = match &self.prev {
-            Some(prev_unwrapped) => {
-                prev_unwrapped.clone().result(&mut arr);
-                arr
-            }
-            None => arr,
-        };
-
-        arr
+        match &self.prev {
+            Some(prev_unwrapped) => hash_str_bytes(prev_unwrapped),
+            None => hash_array(),
+        }
     }
 }
 
@@ -171,46 +327,65 @@ impl Hashable for Block {
     // generate vec of bytes with information given in the Block.
     // hash for self is not included.
     fn bytes(&self) -> Vec<u8> {
+        header_bytes(
+            self.index,
+            self.timestamp,
+            &self.prev,
+            self.nonce,
+            &self.tx_root,
+            self.difficulty,
+            &self.state_root,
+        )
+    }
+}
+
+impl Debug for Block {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "index - {}\n block hash - {},\n timestamp - {},\n transaction - {:?}",
+            self.index, self.hash, self.timestamp, self.transactions
+        )
+    }
+}
+
+impl Encodable for Block {
+    fn encode(&self) -> Vec<u8> {
         let mut result = vec![];
 
         result.extend(u64_bytes(&self.index));
         result.extend(u64_bytes(&self.timestamp));
-
-        let prev_has_str = self.prev_hash_str();
-
-        //put if prev exists.
-        if prev_has_str != "" {
-            result.extend(prev_has_str.as_bytes());
-        }
-
-        let tx_vec = self
-            .transactions
-            .values()
-            .iter()
-            .flat_map(|x| {
-                let mut hash_arr = hash_array();
-                x.hash().result(&mut hash_arr);
-                hash_arr
-            })
-            .collect::<Vec<u8>>();
-
+        put_opt_str(&mut result, &self.prev);
         result.extend(u64_bytes(&self.nonce));
-        result.extend(tx_vec);
         result.extend(u128_bytes(&self.difficulty));
+        result.extend(self.transactions.encode());
+        put_str(&mut result, &self.hash);
+        put_str(&mut result, &self.broadcaster_addr);
+        put_opt_str(&mut result, &self.state_root);
 
         result
     }
-}
 
-impl Debug for Block {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let hash_str = self.hash.clone().result_str();
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        let index = reader.u64()?;
+        let timestamp = reader.u64()?;
+        let prev = reader.opt_string()?;
+        let nonce = reader.u64()?;
+        let difficulty = reader.u128()?;
+        let transactions = Transactions::decode(reader)?;
 
-        write!(
-            f,
-            "index - {}\n block hash - {},\n timestamp - {},\n transaction - {:?}",
-            self.index, hash_str, self.timestamp, self.transactions
-        )
+        Ok(Block {
+            index,
+            timestamp,
+            prev,
+            nonce,
+            difficulty,
+            tx_root: transactions.root(),
+            transactions,
+            hash: reader.string()?,
+            broadcaster_addr: reader.string()?,
+            state_root: reader.opt_string()?,
+        })
     }
 }
 
@@ -250,5 +425,79 @@ fn _mint_virtual_block() -> Block {
     hasher.input(letter);
 
     // Block::new(0, None, String::from_utf8(letter.to_vec()).unwrap())
-    Block::new(0, None, Transactions::empty(), hasher.result_str())
+    Block::new(
+        0,
+        None,
+        Transactions::empty(),
+        hasher.result_str(),
+        MAINNET_DIFFICULTY,
+        1_000,
+    )
+}
+
+#[test]
+fn test_encode_roundtrip() {
+    let mut transactions = Transactions::empty();
+    transactions.values_mut().push(Transaction::new(
+        get_hash("Bob".to_string()),
+        get_hash("Tom".to_string()),
+        7,
+    ));
+
+    let mut block = Block::new(
+        3,
+        None,
+        transactions,
+        get_hash("Broadcaster".to_string()),
+        MAINNET_DIFFICULTY,
+        1_000,
+    );
+    block.set_prev_block(get_genesis_hasher());
+
+    let decoded = Block::from_bytes(&block.encode()).unwrap();
+
+    assert_eq!(decoded.encode(), block.encode());
+    assert_eq!(decoded.prev_hash_str(), get_genesis_hasher());
+    assert_eq!(decoded.transactions().values()[0].value(), &7);
+    assert!(Block::from_bytes(&block.encode()[..20]).is_err());
+}
+
+#[test]
+fn test_header_hash() {
+    use crate::backend::traits::clock::ManualClock;
+
+    let mut transactions = Transactions::empty();
+    transactions.values_mut().push(Transaction::new(
+        get_hash("Bob".to_string()),
+        get_hash("Tom".to_string()),
+        7,
+    ));
+
+    let clock = ManualClock::new(1_000);
+    let block = Block::mine_with_at(
+        1,
+        transactions,
+        get_hash("Broadcaster".to_string()),
+        MAINNET_DIFFICULTY,
+        &clock,
+    );
+    let mut header = block.header();
+
+    assert_eq!(header.hash().result_str(), *block.current_hash());
+    assert_eq!(header.tx_root(), &block.transactions().root());
+    assert_eq!(BlockHeader::from_bytes(&header.encode()).unwrap(), header);
+    assert!(check_difficulty(
+        &header.current_hash_bytes(),
+        &header.difficulty()
+    ));
+
+    header = Block::mine_with_at(
+        1,
+        Transactions::empty(),
+        block.broadcaster().clone(),
+        MAINNET_DIFFICULTY,
+        &clock,
+    )
+    .header();
+    assert_ne!(header.tx_root(), &block.transactions().root());
 }
@@ -1,15 +1,103 @@
 use core::fmt::Debug;
-use std::collections::VecDeque;
+
+use std::collections::{HashMap, VecDeque};
 
 use crypto::digest::Digest;
 
-use crate::backend::types::Hash;
-use crate::backend::{enums::BlockValidationError, functions::*, Block, Hashable};
-use crate::backend::{Transaction, ValueStore};
+use crate::backend::enums::StoreError;
+use crate::backend::store::chainstore::{ChainStore, WriteBatch};
+use crate::backend::store::memory::MemoryStore;
+
+use super::finality::{FinalityTracker, Vote};
+use super::orphan::OrphanPool;
+use super::tree::{block_work, BlockTree, TreeNode};
+use crate::backend::params::{ChainParams, Finality};
+use crate::backend::traits::clock::{Clock, SystemClock};
+use crate::backend::types::{Hash, StateWrite};
+use crate::backend::{enums::BlockValidationError, functions::*, Block, BlockHeader, Hashable};
+use crate::backend::{Receipt, SpendContext, Transaction, ValueStore};
+
+// how many blocks pass between two state snapshots by default.
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 1000;
 
-pub struct BlockChain {
-    blocks: Vec<Block>,
+pub struct BlockChain<S: ChainStore = MemoryStore> {
+    store: S,
     value_store: ValueStore,
+    snapshot_interval: u64,
+    // keep the bodies of only this many recent blocks.
+    prune_keep: Option<u64>,
+    tree: BlockTree,
+    orphans: OrphanPool,
+    params: ChainParams,
+    finality: FinalityTracker,
+    clock: Box<dyn Clock>,
+    // receipts of the contract transactions on the active chain, by transaction id.
+    // only blocks applied since the chain was opened have them.
+    receipts: HashMap<Hash, Receipt>,
+}
+
+// blocks leaving and joining the active chain through one update.
+// disconnected blocks are ordered from the old tip down, connected ones upwards.
+#[derive(Debug, Default)]
+pub struct ChainUpdate {
+    disconnected: Vec<Block>,
+    connected: Vec<Block>,
+    // orphans which turned out invalid once their parent arrived, with the reason.
+    dropped_orphans: Vec<(Hash, BlockValidationError)>,
+    // height of the last connected block, the new tip.
+    tip_height: Option<u64>,
+}
+
+impl ChainUpdate {
+    fn connect(block: Block, height: u64) -> Self {
+        ChainUpdate {
+            connected: vec![block],
+            tip_height: Some(height),
+            ..Default::default()
+        }
+    }
+
+    pub fn disconnected(&self) -> &Vec<Block> {
+        &self.disconnected
+    }
+
+    pub fn connected(&self) -> &Vec<Block> {
+        &self.connected
+    }
+
+    pub fn dropped_orphans(&self) -> &Vec<(Hash, BlockValidationError)> {
+        &self.dropped_orphans
+    }
+
+    pub fn tip_height(&self) -> Option<u64> {
+        self.tip_height
+    }
+
+    pub fn is_reorg(&self) -> bool {
+        !self.disconnected.is_empty()
+    }
+
+    // fold in the update of a later block. a block connected and disconnected again
+    // in between is left out of both lists.
+    fn merge(&mut self, other: ChainUpdate) {
+        for block in other.disconnected {
+            let connected = self
+                .connected
+                .iter()
+                .position(|connected| connected.current_hash() == block.current_hash());
+
+            match connected {
+                Some(idx) => {
+                    self.connected.remove(idx);
+                }
+                None => self.disconnected.push(block),
+            }
+        }
+
+        self.connected.extend(other.connected);
+        self.dropped_orphans.extend(other.dropped_orphans);
+        self.tip_height = other.tip_height.or(self.tip_height);
+    }
 }
 
 // Blockchain - contains block infos, and values with address inside.
@@ -17,43 +105,273 @@ pub struct BlockChain {
 
 impl BlockChain {
     pub fn new() -> BlockChain {
+        Self::with_params(ChainParams::default())
+    }
+
+    pub fn with_params(params: ChainParams) -> BlockChain {
         BlockChain {
-            blocks: vec![],
+            store: MemoryStore::new(),
             value_store: ValueStore::new(),
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            prune_keep: None,
+            tree: BlockTree::new(),
+            orphans: OrphanPool::default(),
+            params,
+            finality: FinalityTracker::new(),
+            clock: Box::new(SystemClock),
+            receipts: HashMap::new(),
         }
     }
+}
+
+impl Default for BlockChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: ChainStore> BlockChain<S> {
+    // resume the chain kept in the given store.
+    // the state is restored from the latest snapshot, and only later blocks are replayed.
+    // undo data is kept in memory only, so after a restart blocks up to the snapshot can't be
+    // disconnected anymore, and a reorg below it fails with `ReorgTooDeep`.
+    pub fn with_store(store: S) -> Result<BlockChain<S>, StoreError> {
+        Self::with_store_and_params(store, ChainParams::default())
+    }
+
+    // the stored chain has to agree with the checkpoints of `params`.
+    pub fn with_store_and_params(
+        store: S,
+        params: ChainParams,
+    ) -> Result<BlockChain<S>, StoreError> {
+        let (value_store, from) = match store.latest_snapshot()? {
+            Some(snapshot) => (
+                ValueStore::import_snapshot(&snapshot)?,
+                snapshot.height() + 1,
+            ),
+            None => (ValueStore::new(), 0),
+        };
+
+        let mut chain = BlockChain {
+            store,
+            value_store,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            prune_keep: None,
+            tree: BlockTree::new(),
+            orphans: OrphanPool::default(),
+            params,
+            finality: FinalityTracker::new(),
+            clock: Box::new(SystemClock),
+            receipts: HashMap::new(),
+        };
+
+        chain.replay_blocks(from)?;
+        chain.update_finality()?;
+
+        Ok(chain)
+    }
+
+    // take a state snapshot every `interval` blocks. 0 turns snapshots off.
+    pub fn set_snapshot_interval(&mut self, interval: u64) {
+        self.snapshot_interval = interval;
+    }
+
+    // keep only the last `keep` full blocks, plus every header. `None` keeps everything.
+    // bodies newer than the latest snapshot are never dropped, they are needed to restore the state.
+    pub fn set_pruning(&mut self, keep: Option<u64>) {
+        self.prune_keep = keep;
+    }
+
+    // bring the state up to the stored tip by applying the blocks it hasn't seen.
+    fn replay_blocks(&mut self, from: u64) -> Result<(), StoreError> {
+        let to = self.store.block_count();
+
+        for height in 0..to {
+            let header = self
+                .store
+                .header_by_height(height)?
+                .ok_or(StoreError::MissingBlock(height))?;
+
+            if !self
+                .params
+                .matches_checkpoint(height, header.current_hash())
+            {
+                return Err(StoreError::InvalidStoredBlock(height));
+            }
+
+            if !check_difficulty(&header.current_hash_bytes(), &header.difficulty()) {
+                return Err(StoreError::InvalidStoredBlock(height));
+            }
 
-    pub fn last(&self) -> Option<&Block> {
-        if self.blocks.len() == 0 {
-            return None;
+            self.tree.set_active(height, header.difficulty());
         }
 
-        Some(self.blocks.last().unwrap())
+        if from == to {
+            return Ok(());
+        }
+
+        for height in from..to {
+            let block = self
+                .store
+                .block_by_height(height)?
+                .ok_or(StoreError::MissingBlock(height))?;
+
+            let mark = self.value_store.journal_len();
+            match self.apply_block(height, &block) {
+                Ok(receipts) => self.insert_receipts(receipts),
+                Err(_) => return Err(StoreError::InvalidStoredBlock(height)),
+            }
+            let undo = self.value_store.undo_since(mark);
+            self.tree.set_undo(block.current_hash_str(), undo);
+        }
+
+        let mut batch = WriteBatch::new();
+        batch.extend_state(self.value_store.commit());
+        self.store.write(batch)
+    }
+
+    // time source for validating block timestamps.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    pub fn params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn last(&self) -> Option<Block> {
+        self.store.last().ok().flatten()
+    }
+
+    pub fn last_header(&self) -> Option<BlockHeader> {
+        self.store.last_header().ok().flatten()
+    }
+
+    pub fn get_block_size(&self) -> usize {
+        self.store.block_count() as usize
+    }
+
+    pub fn block_by_hash(&self, hash: &Hash) -> Result<Option<Block>, StoreError> {
+        self.store.block_by_hash(hash)
     }
 
-    pub fn get_block_size(&self) -> usize{
-        self.blocks.len()
+    pub fn block_by_height(&self, height: u64) -> Result<Option<Block>, StoreError> {
+        self.store.block_by_height(height)
+    }
+
+    pub fn header_by_hash(&self, hash: &Hash) -> Result<Option<BlockHeader>, StoreError> {
+        self.store.header_by_hash(hash)
+    }
+
+    pub fn header_by_height(&self, height: u64) -> Result<Option<BlockHeader>, StoreError> {
+        self.store.header_by_height(height)
     }
 
     pub fn value_store(&self) -> &ValueStore {
         &self.value_store
     }
 
+    pub fn receipt(&self, tx_id: &Hash) -> Option<&Receipt> {
+        self.receipts.get(tx_id)
+    }
+
+    fn insert_receipts(&mut self, receipts: Vec<Receipt>) {
+        for receipt in receipts {
+            self.receipts.insert(receipt.tx_id().clone(), receipt);
+        }
+    }
+
     fn _push(&mut self, block: Block) {
-        self.blocks.push(block);
+        let height = self.store.block_count();
+        self.tree.set_active(height, block.difficulty());
+
+        let mut batch = WriteBatch::new();
+        batch.put_block(height, block);
+        self.store.write(batch).unwrap();
+    }
+
+    // add a block to the tree. it extends the active chain, waits on a side branch,
+    // or makes its branch the active one if that has more cumulative work.
+    // blocks mined without a parent extend the current tip.
+    // a block whose parent is unknown is kept in the orphan pool, and submitted again
+    // together with the other waiting children once the parent is added.
+    pub fn update_block(&mut self, new_block: Block) -> Result<ChainUpdate, BlockValidationError> {
+        self.orphans.expire(self.clock.now());
+
+        let mut parents = vec![new_block.current_hash_str()];
+        let mut update = self.accept_block(new_block)?;
+
+        while let Some(parent) = parents.pop() {
+            for child in self.orphans.take_children(&parent) {
+                let hash = child.current_hash_str();
+
+                match self.accept_block(child) {
+                    Ok(child_update) => {
+                        update.merge(child_update);
+                        parents.push(hash);
+                    }
+                    Err(e) => update.dropped_orphans.push((hash, e)),
+                }
+            }
+        }
+
+        Ok(update)
+    }
+
+    // parents of orphan blocks which are not known yet, to be requested from peers.
+    pub fn missing_parents(&self) -> Vec<Hash> {
+        self.orphans.missing_parents()
+    }
+
+    pub fn orphans(&self) -> &OrphanPool {
+        &self.orphans
+    }
+
+    // bound the orphan pool by count and by age in nanoseconds.
+    pub fn set_orphan_limits(&mut self, max_size: usize, max_age: u64) {
+        self.orphans = OrphanPool::new(max_size, max_age);
     }
 
-    pub fn update_block(&mut self, mut new_block: Block) -> Result<(), BlockValidationError> {
+    fn accept_block(&mut self, mut new_block: Block) -> Result<ChainUpdate, BlockValidationError> {
+        self.check_size_limits(&new_block)?;
+
         // if blockchain is empty
-        if self.blocks.len() == 0 {
+        if self.store.block_count() == 0 {
             // check genesis block
-            if new_block.index() != 0 {
+            if new_block.index() != 0 || new_block.prev().is_some() {
                 println!("Please check index of genesis block.");
                 return Err(BlockValidationError::MismatchedIndex);
             }
 
-            let result =
-                check_difficulty(&mut new_block.current_hash_bytes(), &new_block.difficulty());
+            if !self.params.matches_checkpoint(0, new_block.current_hash()) {
+                return Err(BlockValidationError::CheckpointMismatch);
+            }
+
+            // the genesis block of another network.
+            let transactions = new_block.transactions().values();
+            if transactions.is_empty()
+                || !transactions
+                    .iter()
+                    .all(|tx| self.params.is_own_coinbase_input(tx.from_address()))
+            {
+                return Err(BlockValidationError::InvalidGenesisBlock);
+            }
+
+            if new_block.difficulty() != self.params.difficulty() {
+                return Err(BlockValidationError::MismatchedDifficulty);
+            }
+
+            self.check_future_drift(&new_block)?;
+
+            let result = check_difficulty(&new_block.current_hash_bytes(), &new_block.difficulty());
 
             if !result {
                 return Err(BlockValidationError::InvalidHash);
@@ -61,78 +379,644 @@ impl BlockChain {
 
             // transact one transaction on genesis block
             // apply exception to this.
-            if let Ok(()) = self.value_store.insert_genesis_balance(&mut new_block) {
-                self.blocks.push(new_block);
-                println!("Successful Genesis Block inserting!");
-                
-                return Ok(());
-            } else {
-                return Err(BlockValidationError::InvalidInput);
-            }
+            self.apply_block(0, &new_block)?;
+            self.commit_blocks(0, vec![(new_block.clone(), vec![])])?;
+            println!("Successful Genesis Block inserting!");
+
+            return Ok(ChainUpdate::connect(new_block, 0));
         }
 
         // normal blokchain update.
+        let hash = new_block.current_hash_str();
 
-        if self.blocks.len() >= new_block.index() as usize {
-            return Err(BlockValidationError::MismatchedIndex);
+        if self.store.height_of(&hash).is_some()
+            || self.tree.side_block(&hash).is_some()
+            || self.orphans.contains(&hash)
+        {
+            return Err(BlockValidationError::KnownBlock);
+        }
+
+        if new_block.hash().result_str() != hash {
+            return Err(BlockValidationError::InvalidHash);
+        }
+
+        self.check_future_drift(&new_block)?;
+
+        if let Some(prev) = new_block.prev() {
+            if self.store.height_of(prev).is_none() && self.tree.side_block(prev).is_none() {
+                // checked against its own difficulty, so the pool can't be filled for free.
+                if !check_difficulty(&new_block.current_hash_bytes(), &new_block.difficulty()) {
+                    return Err(BlockValidationError::InvalidHash);
+                }
+
+                self.orphans.insert(new_block, self.clock.now());
+                return Err(BlockValidationError::UnknownParent);
+            }
         }
 
-        let last_block = self.blocks.last().unwrap();
+        // only the header is needed, so this works on a pruned store too.
+        let tip = self.store.last_header()?.unwrap();
+        let tip_height = self.store.block_count() - 1;
+
+        let (parent, parent_height, parent_work) = match new_block.prev() {
+            None => (tip.clone(), tip_height, self.tree.tip_work()),
+            Some(prev) => self.parent_of(prev)?,
+        };
+
+        if parent.difficulty() != new_block.difficulty() {
+            return Err(BlockValidationError::MismatchedDifficulty);
+        }
 
-        let is_valid_hash =
-            check_difficulty(&new_block.current_hash_bytes(), &last_block.difficulty());
+        let is_valid_hash = check_difficulty(&new_block.current_hash_bytes(), &parent.difficulty());
         if !is_valid_hash {
             return Err(BlockValidationError::InvalidHash);
         }
 
-        if last_block.timestamp() >= new_block.timestamp() {
-            println!("genesis_block - {}, other - {}", last_block.timestamp(), new_block.timestamp());
-            return Err(BlockValidationError::AchronologicalTimestamp);
+        if parent.index() >= new_block.index() {
+            return Err(BlockValidationError::MismatchedIndex);
+        }
+
+        let median_time_past = self.median_time_past(&parent)?;
+        if new_block.timestamp() <= median_time_past {
+            return Err(BlockValidationError::TimestampBeforeMedianTimePast);
         }
 
-        let mut processed_txs = VecDeque::new();
+        let height = parent_height + 1;
+        if !self.params.matches_checkpoint(height, &hash) {
+            return Err(BlockValidationError::CheckpointMismatch);
+        }
 
-        // insert Block data
-        for tx in new_block.transactions().values().iter() {
-            if let Ok(()) = self.insert_value_to_balance(tx) {
-                processed_txs.push_back(tx);
-            } else {
-                //rollback txs
-                while !processed_txs.is_empty() {
-                    let tx_done = processed_txs.pop_front().unwrap();
-                    _ = self.set_transaction(tx_done.to_address(), tx_done.from_address(), *tx.value());
+        // extending the tip is the common case.
+        if parent.current_hash() == tip.current_hash() {
+            new_block.set_prev_block(tip.current_hash_str());
+
+            let mark = self.value_store.journal_len();
+            let receipts = self.apply_block(height, &new_block)?;
+
+            let undo = self.value_store.undo_since(mark);
+            self.commit_blocks(height, vec![(new_block.clone(), undo)])?;
+            self.insert_receipts(receipts);
+
+            return Ok(ChainUpdate::connect(new_block, height));
+        }
+
+        if self
+            .finality
+            .finalized()
+            .is_some_and(|finalized| height <= finalized)
+        {
+            return Err(BlockValidationError::FinalityConflict);
+        }
+
+        // a branch forking below a checkpoint already on the active chain can never replace it.
+        if let Some((checkpoint, _)) = self.params.last_checkpoint_below(tip_height) {
+            if height <= checkpoint {
+                return Err(BlockValidationError::CheckpointMismatch);
+            }
+        }
+
+        let work = parent_work.saturating_add(block_work(new_block.difficulty()));
+        self.tree
+            .insert_side(TreeNode::new(new_block, height, work));
+
+        // ties keep the branch which was seen first.
+        if work > self.tree.tip_work() {
+            return self.reorg(&hash);
+        }
+
+        Ok(ChainUpdate::default())
+    }
+
+    fn check_size_limits(&self, block: &Block) -> Result<(), BlockValidationError> {
+        let transactions = block.transactions().values();
+        if transactions.len() > self.params.max_block_transactions() {
+            return Err(BlockValidationError::TooManyTransactions {
+                count: transactions.len(),
+                max_block_transactions: self.params.max_block_transactions(),
+            });
+        }
+
+        for transaction in transactions.iter() {
+            if transaction.size() > self.params.max_tx_size() {
+                return Err(BlockValidationError::TransactionTooLarge {
+                    size: transaction.size(),
+                    max_tx_size: self.params.max_tx_size(),
+                });
+            }
+        }
+
+        if block.size() > self.params.max_block_size() {
+            return Err(BlockValidationError::BlockTooLarge {
+                size: block.size(),
+                max_block_size: self.params.max_block_size(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn check_transaction(&self, tx: &Transaction) -> Result<(), BlockValidationError> {
+        let size = tx.data().len();
+        if size > self.params.max_tx_data() {
+            return Err(BlockValidationError::DataTooLarge {
+                size,
+                max_tx_data: self.params.max_tx_data(),
+            });
+        }
+
+        let required = self.params.data_fee(size);
+        if tx.fee() < required {
+            return Err(BlockValidationError::InsufficientDataFee {
+                fee: tx.fee(),
+                required,
+            });
+        }
+
+        Ok(())
+    }
+
+    // scripts and signatures below the assumed valid block aren't checked again. only the
+    // chain leading to it can get past its height, see `ChainParams::matches_checkpoint`.
+    fn is_assumed_valid(&self, height: u64) -> bool {
+        self.params
+            .assumed_valid()
+            .is_some_and(|(assumed, _)| height < assumed)
+    }
+
+    // blocks may not claim a time too far ahead of ours.
+    fn check_future_drift(&self, block: &Block) -> Result<(), BlockValidationError> {
+        let limit = self
+            .clock
+            .now()
+            .saturating_add(self.params.max_future_drift());
+
+        if block.timestamp() > limit {
+            return Err(BlockValidationError::TimestampTooFarInFuture);
+        }
+
+        Ok(())
+    }
+
+    // median timestamp of `parent` and the blocks before it, on whichever branch it is.
+    pub fn median_time_past(&self, parent: &BlockHeader) -> Result<u64, BlockValidationError> {
+        let mut timestamps = vec![parent.timestamp()];
+        let mut current = parent.prev_hash_str();
+
+        while timestamps.len() < self.params.median_time_span() && !current.is_empty() {
+            let header = match self.tree.side_block(&current) {
+                Some(node) => node.block().header(),
+                None => self
+                    .store
+                    .header_by_hash(&current)?
+                    .ok_or(BlockValidationError::UnknownParent)?,
+            };
+
+            timestamps.push(header.timestamp());
+            current = header.prev_hash_str();
+        }
+
+        Ok(median_timestamp(timestamps))
+    }
+
+    // header, height and cumulative work of a block on any branch.
+    fn parent_of(&self, hash: &Hash) -> Result<(BlockHeader, u64, u128), BlockValidationError> {
+        if let Some(node) = self.tree.side_block(hash) {
+            return Ok((node.block().header(), node.height(), node.work()));
+        }
+
+        let height = self
+            .store
+            .height_of(hash)
+            .ok_or(BlockValidationError::UnknownParent)?;
+        let header = self
+            .store
+            .header_by_height(height)?
+            .ok_or(StoreError::MissingBlock(height))?;
+        let work = self
+            .tree
+            .active_work(height)
+            .ok_or(StoreError::MissingBlock(height))?;
+
+        Ok((header, height, work))
+    }
+
+    // switch the active chain to the branch ending at the side block `tip`.
+    // the old blocks are reverted with their undo data and the new ones applied in order.
+    // if a new block turns out invalid it is dropped with its descendants and nothing changes.
+    fn reorg(&mut self, tip: &Hash) -> Result<ChainUpdate, BlockValidationError> {
+        let branch = self.tree.branch(tip);
+        let fork_height = self.tree.side_block(&branch[0]).unwrap().height() - 1;
+        let count = self.store.block_count();
+
+        if self
+            .finality
+            .finalized()
+            .is_some_and(|finalized| fork_height < finalized)
+        {
+            return Err(BlockValidationError::FinalityConflict);
+        }
+
+        // gather everything first, so a missing body or undo leaves the chain untouched.
+        let mut disconnected = vec![];
+        for height in (fork_height + 1..count).rev() {
+            let block = self
+                .store
+                .block_by_height(height)?
+                .ok_or(StoreError::MissingBlock(height))?;
+            let undo = self
+                .tree
+                .undo(block.current_hash())
+                .ok_or(BlockValidationError::ReorgTooDeep)?
+                .clone();
+            disconnected.push((block, undo));
+        }
+
+        for (_, undo) in disconnected.iter() {
+            if let Err(e) = self.value_store.apply_writes(undo) {
+                self.value_store.rollback();
+                return Err(StoreError::from(e).into());
+            }
+        }
+
+        let mut connected = vec![];
+        let mut receipts = vec![];
+        for (offset, hash) in branch.iter().enumerate() {
+            let block = self.tree.side_block(hash).unwrap().block().clone();
+            let height = fork_height + 1 + offset as u64;
+
+            let mark = self.value_store.journal_len();
+            match self.apply_block(height, &block) {
+                Ok(block_receipts) => receipts.extend(block_receipts),
+                Err(e) => {
+                    // `apply_block` rolled back the disconnects as well.
+                    self.tree.remove_side_branch(hash);
+                    return Err(e);
                 }
-                //exit the function.
-                return Err(BlockValidationError::InvalidConinbaseTransaction);
             }
+
+            let undo = self.value_store.undo_since(mark);
+            connected.push((block, undo));
         }
 
-        let broadcaster = new_block.broadcaster().clone();
-        new_block.set_prev_block(*self.blocks.last().unwrap().current_hash());
-        self.blocks.push(new_block);
+        let old_work = (fork_height + 1..count)
+            .map(|height| self.tree.active_work(height).unwrap_or(0))
+            .collect::<Vec<u128>>();
 
-        // add miners reward.
-        self.value_store.reward_miner(&self.blocks, broadcaster);
+        self.commit_blocks(fork_height + 1, connected.clone())?;
+
+        for (block, _) in disconnected.iter() {
+            for tx in block.transactions().values() {
+                self.receipts.remove(&tx.id());
+            }
+        }
+        self.insert_receipts(receipts);
+
+        for (offset, ((block, _), work)) in disconnected.iter().rev().zip(old_work).enumerate() {
+            let height = fork_height + 1 + offset as u64;
+            self.tree.remove_undo(block.current_hash());
+            self.tree
+                .insert_side(TreeNode::new(block.clone(), height, work));
+        }
+        for (block, _) in connected.iter() {
+            self.tree.remove_side(block.current_hash());
+        }
 
-        return Ok(());
+        Ok(ChainUpdate {
+            disconnected: disconnected.into_iter().map(|(block, _)| block).collect(),
+            tip_height: Some(fork_height + connected.len() as u64),
+            connected: connected.into_iter().map(|(block, _)| block).collect(),
+            ..Default::default()
+        })
     }
 
-    fn insert_value_to_balance(&mut self, tx: &Transaction) -> Result<(), BlockValidationError> {
-        // should not be applied without genesis block.
-        if self.blocks.len() == 0 {
-            return Err(BlockValidationError::InvalidInput);
+    // apply a block, which has to lead to the state root it commits to.
+    // nothing is kept on failure. gives back the receipts of its contract transactions.
+    fn apply_block(
+        &mut self,
+        height: u64,
+        block: &Block,
+    ) -> Result<Vec<Receipt>, BlockValidationError> {
+        let receipts = self.apply_transactions(height, block)?;
+
+        if block.state_root() != Some(&self.value_store.commitment()) {
+            self.value_store.rollback();
+            return Err(BlockValidationError::MismatchedStateRoot);
+        }
+
+        Ok(receipts)
+    }
+
+    // commit `block` to the state root it leads to on top of its parent, or on top of the tip
+    // if it has none. the block is mined again.
+    pub fn seal_block(&mut self, block: Block) -> Result<Block, BlockValidationError> {
+        let parent = match block.prev() {
+            Some(prev) => prev.clone(),
+            None => self
+                .last_header()
+                .ok_or(BlockValidationError::UnknownParent)?
+                .current_hash_str(),
+        };
+
+        let root = self.state_root_after(&parent, &block)?;
+        Ok(block.with_state_root(root))
+    }
+
+    // root of the state after `block` on top of `parent`, which may be on a side branch.
+    // the values are left as they were.
+    pub(crate) fn state_root_after(
+        &mut self,
+        parent: &Hash,
+        block: &Block,
+    ) -> Result<Hash, BlockValidationError> {
+        let result = self.rewind_to(parent).and_then(|height| {
+            self.apply_transactions(height + 1, block)?;
+            Ok(self.value_store.commitment())
+        });
+        self.value_store.rollback();
+
+        result
+    }
+
+    // bring the values to the state after `parent`, the way a reorg would, without committing.
+    // gives back the height of `parent`.
+    fn rewind_to(&mut self, parent: &Hash) -> Result<u64, BlockValidationError> {
+        let (_, parent_height, _) = self.parent_of(parent)?;
+        let branch = self.tree.branch(parent);
+        let fork_height = parent_height - branch.len() as u64;
+
+        for height in (fork_height + 1..self.store.block_count()).rev() {
+            let header = self
+                .store
+                .header_by_height(height)?
+                .ok_or(StoreError::MissingBlock(height))?;
+            let undo = self
+                .tree
+                .undo(header.current_hash())
+                .ok_or(BlockValidationError::ReorgTooDeep)?
+                .clone();
+            self.value_store
+                .apply_writes(&undo)
+                .map_err(StoreError::from)?;
+        }
+
+        for (offset, hash) in branch.iter().enumerate() {
+            let block = self.tree.side_block(hash).unwrap().block().clone();
+            self.apply_block(fork_height + 1 + offset as u64, &block)?;
+        }
+
+        Ok(parent_height)
+    }
+
+    // apply one transaction of a block at `height` on top of the ones before it. a failing
+    // transaction leaves no change behind, the earlier ones are kept until `discard_changes`.
+    pub(crate) fn apply_block_transaction(
+        &mut self,
+        tx: &Transaction,
+        miner: &Hash,
+        height: u64,
+        timestamp: u64,
+    ) -> Result<Option<Receipt>, BlockValidationError> {
+        tx.check_validity(height, timestamp)?;
+        self.check_transaction(tx)?;
+        self.insert_value_to_balance(tx, miner, height, timestamp)
+    }
+
+    // drop the changes of transactions applied since the last block.
+    pub(crate) fn discard_changes(&mut self) {
+        self.value_store.rollback();
+    }
+
+    // apply the transactions of a block to the values. nothing is kept on failure.
+    fn apply_transactions(
+        &mut self,
+        height: u64,
+        block: &Block,
+    ) -> Result<Vec<Receipt>, BlockValidationError> {
+        if height == 0 {
+            if self.value_store.insert_genesis_balance(block).is_err() {
+                self.value_store.rollback();
+                return Err(BlockValidationError::InvalidInput);
+            }
+
+            return Ok(vec![]);
         }
 
-        let from_adr = tx.from_address();
-        let to_adr = tx.to_address();
+        let mut receipts = vec![];
 
-        let value = tx.value();
+        // insert Block data
+        for tx in block.transactions().values().iter() {
+            match self.apply_block_transaction(tx, block.broadcaster(), height, block.timestamp()) {
+                Ok(receipt) => receipts.extend(receipt),
+                Err(e) => {
+                    //rollback txs
+                    self.value_store.rollback();
+                    //exit the function.
+                    return Err(e);
+                }
+            }
+        }
 
-        if let Err(e) = self.set_transaction(from_adr, to_adr, *value) {
+        // add miners reward.
+        if let Err(e) = self.value_store.reward_miner(
+            Some(block),
+            block.broadcaster().clone(),
+            self.params.block_reward(),
+        ) {
+            self.value_store.rollback();
             return Err(e);
-        } else {
-            return Ok(());
         }
+
+        Ok(receipts)
+    }
+
+    // persist blocks from `from` upwards, replacing any active ones there,
+    // together with the state changes they caused. each comes with its undo data.
+    fn commit_blocks(
+        &mut self,
+        from: u64,
+        blocks: Vec<(Block, Vec<StateWrite>)>,
+    ) -> Result<(), BlockValidationError> {
+        let mut batch = WriteBatch::new();
+        for (offset, (block, _)) in blocks.iter().enumerate() {
+            batch.put_block(from + offset as u64, block.clone());
+        }
+        batch.extend_state(self.value_store.commit());
+
+        if let Err(e) = self.store.write(batch) {
+            // the store is unchanged, so bring the values back in line with it.
+            self.value_store =
+                ValueStore::from_state(self.store.state_entries()?).map_err(StoreError::from)?;
+            return Err(e.into());
+        }
+
+        let mut height = from;
+        let mut hash = String::new();
+        for (block, undo) in blocks {
+            hash = block.current_hash_str();
+            self.tree.set_active(height, block.difficulty());
+            self.tree.set_undo(hash.clone(), undo);
+            height += 1;
+        }
+        let height = height - 1;
+
+        if self.snapshot_interval > 0 && (height + 1).is_multiple_of(self.snapshot_interval) {
+            let snapshot = self.value_store.export_snapshot(height, hash);
+            self.store.put_snapshot(snapshot)?;
+        }
+
+        if let Some(keep) = self.prune_keep {
+            let count = self.store.block_count();
+            let restorable = self.store.latest_snapshot_height().map_or(0, |h| h + 1);
+            let pruned_below = self.store.pruned_below();
+            self.store
+                .prune_bodies(count.saturating_sub(keep).min(restorable))?;
+
+            // pruned blocks can't be disconnected anymore.
+            for height in pruned_below..self.store.pruned_below() {
+                if let Some(header) = self.store.header_by_height(height)? {
+                    self.tree.remove_undo(header.current_hash());
+                }
+            }
+        }
+
+        self.update_finality()?;
+
+        Ok(())
+    }
+
+    // highest block which no reorg can revert.
+    pub fn finalized_height(&self) -> Option<u64> {
+        self.finality.finalized()
+    }
+
+    // count a validator vote. the voted block becomes final once enough validators agree.
+    pub fn add_vote(&mut self, vote: Vote) -> Result<(), BlockValidationError> {
+        self.finality.add_vote(vote, self.params.finality())?;
+        self.update_finality()?;
+
+        Ok(())
+    }
+
+    // move the finalized height forward, and drop what can no longer be needed for a reorg.
+    fn update_finality(&mut self) -> Result<(), StoreError> {
+        let before = self.finality.finalized();
+        let count = self.store.block_count();
+
+        match self.params.finality() {
+            Finality::None => {}
+            Finality::Depth(depth) => {
+                if count > *depth {
+                    self.finality.finalize(count - 1 - depth);
+                }
+            }
+            rule @ Finality::Votes { .. } => {
+                // votes for blocks off the active chain wait until a reorg brings them in.
+                let voted = self
+                    .finality
+                    .voted_blocks(rule)
+                    .into_iter()
+                    .find(|(height, hash)| self.store.height_of(hash) == Some(*height));
+
+                if let Some((height, _)) = voted {
+                    self.finality.finalize(height);
+                }
+            }
+        }
+
+        let finalized = match self.finality.finalized() {
+            Some(finalized) if before != Some(finalized) => finalized,
+            _ => return Ok(()),
+        };
+
+        for height in before.map_or(0, |before| before + 1)..=finalized {
+            if let Some(header) = self.store.header_by_height(height)? {
+                self.tree.remove_undo(header.current_hash());
+            }
+        }
+        self.tree.remove_side_up_to(finalized);
+
+        Ok(())
+    }
+
+    // check the stored chain using headers only, so it also works after pruning.
+    // the state is checked against the commitment recorded in the tip header.
+    pub fn verify_headers(&self) -> Result<(), BlockValidationError> {
+        let mut prev: Option<BlockHeader> = None;
+        let mut recent = VecDeque::new();
+
+        for height in 0..self.store.block_count() {
+            let header = self
+                .store
+                .header_by_height(height)?
+                .ok_or(StoreError::MissingBlock(height))?;
+
+            if !check_difficulty(&header.current_hash_bytes(), &header.difficulty()) {
+                return Err(BlockValidationError::InvalidHash);
+            }
+
+            match &prev {
+                None => {
+                    if header.index() != 0 || !header.prev_hash_str().is_empty() {
+                        return Err(BlockValidationError::InvalidGenesisBlock);
+                    }
+                }
+                Some(prev) => {
+                    if header.index() <= prev.index() {
+                        return Err(BlockValidationError::MismatchedIndex);
+                    }
+
+                    if header.timestamp() <= median_timestamp(recent.iter().copied().collect()) {
+                        return Err(BlockValidationError::TimestampBeforeMedianTimePast);
+                    }
+
+                    if header.prev_hash_str() != *prev.current_hash() {
+                        return Err(BlockValidationError::MismatchedPreviousHash);
+                    }
+                }
+            }
+
+            recent.push_back(header.timestamp());
+            if recent.len() > self.params.median_time_span() {
+                recent.pop_front();
+            }
+
+            prev = Some(header);
+        }
+
+        if let Some(tip) = prev {
+            if tip.state_root() != Some(&self.value_store.commitment()) {
+                return Err(BlockValidationError::MismatchedStateRoot);
+            }
+        }
+
+        Ok(())
+    }
+
+    // contract transactions come with a receipt.
+    fn insert_value_to_balance(
+        &mut self,
+        tx: &Transaction,
+        miner: &Hash,
+        height: u64,
+        timestamp: u64,
+    ) -> Result<Option<Receipt>, BlockValidationError> {
+        // should not be applied without genesis block.
+        if self.store.block_count() == 0 {
+            return Err(BlockValidationError::InvalidInput);
+        }
+
+        let context = SpendContext::new(height, timestamp, &self.params);
+        let check_signatures = !self.is_assumed_valid(height);
+        if tx.is_contract() {
+            return self
+                .value_store
+                .apply_contract_checked(tx, miner, &self.params, &context, check_signatures)
+                .map(Some);
+        }
+
+        self.value_store
+            .apply_checked(tx, miner, &context, check_signatures)
+            .map(|_| None)
     }
 
     pub fn set_transaction(
@@ -145,10 +1029,18 @@ impl BlockChain {
             .set_transaction(from_address, to_address, value)
     }
 
+    fn blocks(&self) -> Vec<Block> {
+        (0..self.store.block_count())
+            .filter_map(|height| self.store.block_by_height(height).ok().flatten())
+            .collect()
+    }
+
     // todo
     // not used currently.
     fn _is_valid(&mut self) -> bool {
-        for (idx, block) in self.blocks.iter_mut().enumerate() {
+        let blocks = self.blocks();
+
+        for (idx, block) in blocks.iter().enumerate() {
             // when block is genesis block
             if block.index() == 0 {
                 if block.prev_hash_str() != "" {
@@ -182,8 +1074,8 @@ impl BlockChain {
         }
 
         //check linearity
-        for (idx, block) in self.blocks.iter().enumerate() {
-            let prev_block = self.blocks.get(idx).unwrap();
+        for (idx, block) in blocks.iter().enumerate() {
+            let prev_block = blocks.get(idx).unwrap();
 
             // check hash validity.
             // check validity of previous hash & previous hash info in current block .
@@ -300,22 +1192,33 @@ impl BlockChain {
     }
 }
 
-impl Debug for BlockChain {
+fn median_timestamp(mut timestamps: Vec<u64>) -> u64 {
+    timestamps.sort_unstable();
+    timestamps.get(timestamps.len() / 2).copied().unwrap_or(0)
+}
+
+impl<S: ChainStore> Debug for BlockChain<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Blockchain")
-            .field("blocks", &self.blocks)
+            .field("blocks", &self.blocks())
             .finish()
     }
 }
 
 #[test]
 fn test_verify() {
+    use crate::backend::params::REGTEST_DIFFICULTY;
     use crate::backend::Transactions;
     use crypto::sha2::Sha256;
 
-    let mut blk_chain = BlockChain::new();
+    let mut blk_chain = BlockChain::with_params(ChainParams::regtest());
 
-    let block = Block::mine(1, Transactions::empty(), Sha256::new().result_str());
+    let block = Block::mine_with(
+        1,
+        Transactions::empty(),
+        Sha256::new().result_str(),
+        REGTEST_DIFFICULTY,
+    );
     blk_chain._push(block);
 
     // println!("{}", blk_chain._is_valid());
@@ -323,35 +1226,40 @@ fn test_verify() {
 
 #[test]
 fn test_verify_blocks() {
-    use crate::backend::Transactions;
+    use crate::backend::{ManualClock, Transactions};
     use crypto::sha2::Sha256;
 
-    let gen_block = Block::gen_genesis();
-    let mut last_hash: Sha256 = *gen_block.current_hash();
-    let mut blk_chain = BlockChain::new();
+    // blocks a second apart, whenever the test runs.
+    let clock = ManualClock::new(1_700_000_000_000_000_000);
+    let gen_block = Block::genesis_at(&ChainParams::regtest(), &clock);
+    let mut last_block = gen_block.clone();
+    let mut blk_chain = BlockChain::with_params(ChainParams::regtest());
+    blk_chain.set_clock(Box::new(clock.clone()));
 
     // put genesis block
-    blk_chain._push(gen_block);
-
-    // let str: &str = "anotehr blcok";
+    blk_chain.update_block(gen_block).unwrap();
 
-    // let mut last_block = None;
     let mut hasher = Sha256::new();
     hasher.input_str("Broadcaster");
 
     for i in 1..=10 {
         println!("{}", i);
-        // let mut block = Block::new(i, Some(last_hash.clone()), vec![]);
-        let block = Block::mine(1, Transactions::empty(), hasher.result_str());
-        // last_block = Some(block.current_hash().clone());
-
-        last_hash = block.current_hash().clone();
-        blk_chain._push(block);
+        clock.advance(1_000_000_000);
+        let block = Block::mine_on_at(
+            &last_block.header(),
+            Transactions::empty(),
+            hasher.result_str(),
+            &clock,
+        );
+        let block = blk_chain.seal_block(block).unwrap();
+
+        last_block = block.clone();
+        blk_chain.update_block(block).unwrap();
     }
 
-    println!("{:?}", last_hash.result_str());
+    println!("{:?}", last_block.current_hash());
     println!("{:?}", blk_chain);
-    println!("{}", blk_chain._is_valid());
+    assert!(blk_chain.verify_headers().is_ok());
 }
 
 #[test]
@@ -359,3 +1267,749 @@ fn test_derefence() {
     let str_a = String::new();
     let _str_b = &str_a;
 }
+
+#[test]
+fn test_reopen_file_store() {
+    use crate::backend::functions::address_of;
+    use crate::backend::params::REGTEST_DIFFICULTY;
+    use crate::backend::store::file::{funded_genesis, temp_store_dir, FileStore};
+    use crate::backend::{Transaction, Transactions};
+    use crypto::ed25519;
+
+    let dir = temp_store_dir("blockchain");
+    let params = ChainParams::regtest();
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let genesis_wallet = address_of(&alice_public);
+    let bob = get_hash("Bob".to_string());
+    let miner = get_hash("Miner".to_string());
+
+    let genesis = funded_genesis(&params, &[(genesis_wallet.clone(), 5000)]);
+
+    let tip = {
+        let mut blk_chain =
+            BlockChain::with_store_and_params(FileStore::open(&dir).unwrap(), params.clone())
+                .unwrap();
+        blk_chain.update_block(genesis).unwrap();
+
+        let mut transactions = Transactions::empty();
+        transactions
+            .values_mut()
+            .push(Transaction::new(genesis_wallet.clone(), bob.clone(), 50).sign(&alice));
+        let block = Block::mine_with(2, transactions, miner.clone(), REGTEST_DIFFICULTY);
+        let block = blk_chain.seal_block(block).unwrap();
+        blk_chain.update_block(block).unwrap();
+
+        blk_chain.last().unwrap().current_hash_str()
+    };
+
+    let blk_chain =
+        BlockChain::with_store_and_params(FileStore::open(&dir).unwrap(), params).unwrap();
+
+    assert_eq!(blk_chain.get_block_size(), 2);
+    assert_eq!(blk_chain.last().unwrap().current_hash_str(), tip);
+    assert!(blk_chain.block_by_hash(&tip).unwrap().is_some());
+    assert_eq!(blk_chain.value_store().balance(&genesis_wallet), Some(4950));
+    assert_eq!(blk_chain.value_store().balance(&bob), Some(50));
+    assert_eq!(blk_chain.value_store().balance(&miner), Some(1));
+
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_restore_from_snapshot() {
+    use crate::backend::functions::address_of;
+    use crate::backend::params::REGTEST_DIFFICULTY;
+    use crate::backend::store::file::{funded_genesis, temp_store_dir, FileStore};
+    use crate::backend::{Transaction, Transactions};
+    use crypto::ed25519;
+
+    let dir = temp_store_dir("replay");
+    let params = ChainParams::regtest();
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let genesis_wallet = address_of(&alice_public);
+    let bob = get_hash("Bob".to_string());
+    let miner = get_hash("Miner".to_string());
+
+    {
+        let mut blk_chain =
+            BlockChain::with_store_and_params(FileStore::open(&dir).unwrap(), params.clone())
+                .unwrap();
+        blk_chain.set_snapshot_interval(2);
+        blk_chain
+            .update_block(funded_genesis(&params, &[(genesis_wallet.clone(), 5000)]))
+            .unwrap();
+
+        for (nonce, (idx, value)) in [(2, 70), (3, 30)].into_iter().enumerate() {
+            let mut transactions = Transactions::empty();
+            transactions.values_mut().push(
+                Transaction::new(genesis_wallet.clone(), bob.clone(), value)
+                    .with_nonce(nonce as u64)
+                    .sign(&alice),
+            );
+            let block = Block::mine_with(idx, transactions, miner.clone(), REGTEST_DIFFICULTY);
+            let block = blk_chain.seal_block(block).unwrap();
+            blk_chain.update_block(block).unwrap();
+        }
+    }
+
+    // the snapshot was taken after the second block. the third one is replayed.
+    let store = FileStore::open(&dir).unwrap();
+    assert_eq!(store.latest_snapshot().unwrap().unwrap().height(), 1);
+
+    let blk_chain = BlockChain::with_store_and_params(store, params).unwrap();
+    assert_eq!(blk_chain.value_store().balance(&bob), Some(100));
+    assert_eq!(blk_chain.value_store().balance(&miner), Some(2));
+    assert_eq!(blk_chain.value_store().balance(&genesis_wallet), Some(4900));
+
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_pruned_chain() {
+    use crate::backend::functions::address_of;
+    use crate::backend::params::REGTEST_DIFFICULTY;
+    use crate::backend::store::file::funded_genesis;
+    use crate::backend::{Transaction, Transactions};
+    use crypto::ed25519;
+
+    let params = ChainParams::regtest();
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let bob = get_hash("Bob".to_string());
+    let miner = get_hash("Miner".to_string());
+
+    let mut blk_chain = BlockChain::with_params(params.clone());
+    blk_chain.set_pruning(Some(1));
+    blk_chain
+        .update_block(funded_genesis(&params, &[(address_of(&alice_public), 100)]))
+        .unwrap();
+
+    for idx in 2..4 {
+        let mut transactions = Transactions::empty();
+        transactions.values_mut().push(
+            Transaction::new(address_of(&alice_public), bob.clone(), 10)
+                .with_nonce(idx - 2)
+                .sign(&alice),
+        );
+        let block = Block::mine_with(idx, transactions, miner.clone(), REGTEST_DIFFICULTY);
+        let block = blk_chain.seal_block(block).unwrap();
+        blk_chain.update_block(block).unwrap();
+    }
+
+    assert!(matches!(
+        blk_chain.block_by_height(1),
+        Err(StoreError::Pruned(1))
+    ));
+    assert!(blk_chain.block_by_height(2).unwrap().is_some());
+    assert_eq!(blk_chain.header_by_height(0).unwrap().unwrap().index(), 0);
+    assert_eq!(blk_chain.value_store().balance(&bob), Some(20));
+    blk_chain.verify_headers().unwrap();
+}
+
+#[test]
+fn test_reorg_to_heavier_branch() {
+    use crate::backend::functions::address_of;
+    use crate::backend::store::file::funded_chain;
+    use crate::backend::{Transaction, Transactions};
+    use crypto::ed25519;
+
+    let params = ChainParams::regtest();
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let bob = get_hash("Bob".to_string());
+    let carol = get_hash("Carol".to_string());
+    let miner = get_hash("Miner".to_string());
+    let pay = |to: &Hash, value: u64| {
+        let mut transactions = Transactions::empty();
+        transactions
+            .values_mut()
+            .push(Transaction::new(address_of(&alice_public), to.clone(), value).sign(&alice));
+        transactions
+    };
+
+    let mut blk_chain = funded_chain(params.clone(), &[(address_of(&alice_public), 100)]);
+    let genesis = blk_chain.last_header().unwrap();
+
+    let a1 = Block::mine_on(&genesis, pay(&bob, 50), miner.clone());
+    let a1 = blk_chain.seal_block(a1).unwrap();
+    let update = blk_chain.update_block(a1.clone()).unwrap();
+    assert_eq!(update.connected().len(), 1);
+
+    // same work as the active chain, so it waits on a side branch.
+    let b1 = Block::mine_on(&genesis, pay(&carol, 30), miner.clone());
+    let b1 = blk_chain.seal_block(b1).unwrap();
+    let update = blk_chain.update_block(b1.clone()).unwrap();
+    assert!(update.connected().is_empty());
+    assert!(matches!(
+        blk_chain.update_block(b1.clone()),
+        Err(BlockValidationError::KnownBlock)
+    ));
+
+    let b2 = Block::mine_on(&b1.header(), Transactions::empty(), miner.clone());
+    let b2 = blk_chain.seal_block(b2).unwrap();
+    let update = blk_chain.update_block(b2.clone()).unwrap();
+    assert!(update.is_reorg());
+    assert_eq!(update.disconnected()[0].current_hash(), a1.current_hash());
+    assert_eq!(
+        update
+            .connected()
+            .iter()
+            .map(|block| block.current_hash_str())
+            .collect::<Vec<Hash>>(),
+        vec![b1.current_hash_str(), b2.current_hash_str()]
+    );
+
+    assert_eq!(blk_chain.get_block_size(), 3);
+    assert_eq!(blk_chain.value_store().balance(&bob), None);
+    assert_eq!(blk_chain.value_store().balance(&carol), Some(30));
+    assert_eq!(blk_chain.value_store().balance(&miner), Some(2));
+    blk_chain.verify_headers().unwrap();
+
+    let orphan_parent = Block::mine_on(&b2.header(), Transactions::empty(), miner.clone());
+    let orphan = Block::mine_on(&orphan_parent.header(), Transactions::empty(), miner);
+    assert!(matches!(
+        blk_chain.update_block(orphan),
+        Err(BlockValidationError::UnknownParent)
+    ));
+}
+
+#[test]
+fn test_orphans_connect_with_parent() {
+    use crate::backend::Transactions;
+
+    let miner = get_hash("Miner".to_string());
+
+    let genesis = Block::genesis(&ChainParams::regtest());
+    let mut blk_chain = BlockChain::with_params(ChainParams::regtest());
+    blk_chain.update_block(genesis.clone()).unwrap();
+
+    // the blocks are built on another node, which has them all.
+    let mut other = BlockChain::with_params(ChainParams::regtest());
+    other.update_block(genesis.clone()).unwrap();
+    let mut parent = genesis;
+    let mut blocks = vec![];
+    for _ in 0..3 {
+        let block = Block::mine_on(&parent.header(), Transactions::empty(), miner.clone());
+        parent = other.seal_block(block).unwrap();
+        other.update_block(parent.clone()).unwrap();
+        blocks.push(parent.clone());
+    }
+    let [first, second, third] = <[Block; 3]>::try_from(blocks).unwrap();
+    // commits to no state, which only shows once it is applied.
+    let bogus = Block::mine_on(&third.header(), Transactions::empty(), miner);
+
+    for block in [bogus.clone(), third.clone(), second.clone()] {
+        assert!(matches!(
+            blk_chain.update_block(block),
+            Err(BlockValidationError::UnknownParent)
+        ));
+    }
+    assert_eq!(blk_chain.orphans().len(), 3);
+    assert_eq!(blk_chain.missing_parents(), vec![first.current_hash_str()]);
+
+    let update = blk_chain.update_block(first).unwrap();
+    assert_eq!(update.connected().len(), 3);
+    assert!(matches!(
+        update.dropped_orphans().as_slice(),
+        [(hash, BlockValidationError::MismatchedStateRoot)] if *hash == bogus.current_hash_str()
+    ));
+    assert_eq!(
+        blk_chain.last().unwrap().current_hash(),
+        third.current_hash()
+    );
+    assert!(blk_chain.orphans().is_empty());
+}
+
+#[test]
+fn test_checkpoint_rejects_branch() {
+    use crate::backend::{ChainParams, Transactions};
+
+    let genesis = Block::genesis(&ChainParams::regtest());
+    let miner = get_hash("Miner".to_string());
+    let mut other = BlockChain::with_params(ChainParams::regtest());
+    other.update_block(genesis.clone()).unwrap();
+    let first = Block::mine_on(&genesis.header(), Transactions::empty(), miner.clone());
+    let first = other.seal_block(first).unwrap();
+    let rival = Block::mine_on(&genesis.header(), Transactions::empty(), miner.clone());
+
+    let rival = other.seal_block(rival).unwrap();
+    other.update_block(first.clone()).unwrap();
+    let second = Block::mine_on(&first.header(), Transactions::empty(), miner);
+    let second = other.seal_block(second).unwrap();
+
+    let params = ChainParams::regtest().with_checkpoint(1, first.current_hash_str());
+    let mut blk_chain = BlockChain::with_params(params);
+    blk_chain.update_block(genesis.clone()).unwrap();
+
+    assert!(matches!(
+        blk_chain.update_block(rival.clone()),
+        Err(BlockValidationError::CheckpointMismatch)
+    ));
+    blk_chain.update_block(first.clone()).unwrap();
+    blk_chain.update_block(second.clone()).unwrap();
+
+    // nothing may fork below a checkpoint once it is on the chain, even where the height
+    // itself has no checkpoint.
+    let params = ChainParams::regtest().with_checkpoint(2, second.current_hash_str());
+    let mut blk_chain = BlockChain::with_params(params);
+    blk_chain.update_block(genesis).unwrap();
+    blk_chain.update_block(first).unwrap();
+    blk_chain.update_block(second).unwrap();
+    assert!(matches!(
+        blk_chain.update_block(rival),
+        Err(BlockValidationError::CheckpointMismatch)
+    ));
+    assert_eq!(blk_chain.get_block_size(), 3);
+}
+
+#[test]
+fn test_depth_finality() {
+    use crate::backend::{ChainParams, Transactions};
+
+    let miner = get_hash("Miner".to_string());
+    let params = ChainParams::regtest().with_finality(Finality::Depth(1));
+    let mut blk_chain = BlockChain::with_params(params);
+
+    blk_chain
+        .update_block(Block::genesis(&ChainParams::regtest()))
+        .unwrap();
+    assert_eq!(blk_chain.finalized_height(), None);
+
+    let genesis = blk_chain.last_header().unwrap();
+    let first = Block::mine_on(&genesis, Transactions::empty(), miner.clone());
+    let first = blk_chain.seal_block(first).unwrap();
+    blk_chain.update_block(first.clone()).unwrap();
+    let second = Block::mine_on(&first.header(), Transactions::empty(), miner.clone());
+    let second = blk_chain.seal_block(second).unwrap();
+    blk_chain.update_block(second).unwrap();
+    assert_eq!(blk_chain.finalized_height(), Some(1));
+
+    // the rival of a final block can't even become a side branch.
+    let rival = Block::mine_on(&genesis, Transactions::empty(), miner);
+    assert!(matches!(
+        blk_chain.update_block(rival),
+        Err(BlockValidationError::FinalityConflict)
+    ));
+}
+
+#[test]
+fn test_networks_reject_foreign_genesis() {
+    let testnet = ChainParams::testnet();
+    let regtest = ChainParams::regtest();
+    let genesis = Block::genesis(&testnet);
+
+    let mut blk_chain = BlockChain::with_params(regtest.clone());
+    assert!(matches!(
+        blk_chain.update_block(genesis.clone()),
+        Err(BlockValidationError::InvalidGenesisBlock)
+    ));
+
+    blk_chain.update_block(Block::genesis(&regtest)).unwrap();
+    assert_ne!(blk_chain.last().unwrap().tx_root(), genesis.tx_root());
+    assert_eq!(
+        blk_chain.value_store().balance(&regtest.genesis_address()),
+        Some(regtest.genesis_balance())
+    );
+}
+
+#[test]
+fn test_reward_overflow() {
+    use crate::backend::Transactions;
+
+    let params = ChainParams::regtest().with_block_reward(u64::MAX);
+    let mut blk_chain = BlockChain::with_params(params.clone());
+    blk_chain.update_block(Block::genesis(&params)).unwrap();
+
+    let first = Block::mine_on(
+        &blk_chain.last_header().unwrap(),
+        Transactions::empty(),
+        "miner".to_string(),
+    );
+    let first = blk_chain.seal_block(first).unwrap();
+    blk_chain.update_block(first.clone()).unwrap();
+
+    // a balance which can't take the reward refuses the block instead of wrapping around.
+    let second = Block::mine_on(&first.header(), Transactions::empty(), "miner".to_string());
+    assert!(matches!(
+        blk_chain.update_block(second),
+        Err(BlockValidationError::InvalidInput)
+    ));
+    assert_eq!(
+        blk_chain.value_store().balance(&"miner".to_string()),
+        Some(u64::MAX)
+    );
+}
+
+#[test]
+fn test_timestamp_rules() {
+    use crate::backend::{ManualClock, Transactions};
+
+    const SECOND: u64 = 1_000_000_000;
+
+    let params = ChainParams::regtest().with_timestamp_rules(3, 10 * SECOND);
+    let local = ManualClock::new(1_000 * SECOND);
+    let mut blk_chain = BlockChain::with_params(params.clone());
+    blk_chain.set_clock(Box::new(local.clone()));
+
+    // what a miner's clock said when its block was made.
+    let stamp = |parent: &Block, timestamp: u64| {
+        Block::mine_on_at(
+            &parent.header(),
+            Transactions::empty(),
+            "a".to_string(),
+            &ManualClock::new(timestamp),
+        )
+    };
+
+    // right at the drift limit is fine, a nanosecond past it is not.
+    let too_early = Block::genesis_at(&params, &ManualClock::new(1_010 * SECOND + 1));
+    assert!(matches!(
+        blk_chain.update_block(too_early),
+        Err(BlockValidationError::TimestampTooFarInFuture)
+    ));
+    let genesis = Block::genesis_at(&params, &ManualClock::new(1_010 * SECOND));
+    blk_chain.update_block(genesis.clone()).unwrap();
+
+    // the median of a single block is its own timestamp, which isn't later than itself.
+    local.advance(100 * SECOND);
+    assert!(matches!(
+        blk_chain.update_block(stamp(&genesis, 1_010 * SECOND)),
+        Err(BlockValidationError::TimestampBeforeMedianTimePast)
+    ));
+    let first = blk_chain
+        .seal_block(stamp(&genesis, 1_050 * SECOND))
+        .unwrap();
+    blk_chain.update_block(first.clone()).unwrap();
+
+    // earlier than its parent, but later than the median of the last three blocks.
+    let second = blk_chain.seal_block(stamp(&first, 1_060 * SECOND)).unwrap();
+    blk_chain.update_block(second.clone()).unwrap();
+    let third = blk_chain
+        .seal_block(stamp(&second, 1_055 * SECOND))
+        .unwrap();
+    blk_chain.update_block(third.clone()).unwrap();
+    assert!(matches!(
+        blk_chain.update_block(stamp(&third, 1_055 * SECOND)),
+        Err(BlockValidationError::TimestampBeforeMedianTimePast)
+    ));
+    assert!(blk_chain.verify_headers().is_ok());
+}
+
+#[test]
+fn test_deterministic_chain() {
+    use crate::backend::functions::address_of;
+    use crate::backend::store::file::funded_genesis;
+    use crate::backend::{Encodable, ManualClock, Transaction, Transactions};
+    use crypto::ed25519;
+
+    let build = || {
+        let clock = ManualClock::new(1_700_000_000_000_000_000);
+        let params = ChainParams::regtest();
+        let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+        let mut parent = funded_genesis(&params, &[(address_of(&alice_public), 100)]);
+        let mut blk_chain = BlockChain::with_params(params);
+        blk_chain.set_clock(Box::new(clock.clone()));
+        blk_chain.update_block(parent.clone()).unwrap();
+
+        for value in 1..=3 {
+            clock.advance(1_000_000_000);
+            let mut transactions = Transactions::empty();
+            transactions.values_mut().push(
+                Transaction::new(address_of(&alice_public), "b".to_string(), value)
+                    .with_nonce(value - 1)
+                    .sign(&alice),
+            );
+
+            parent = Block::mine_on_at(&parent.header(), transactions, "a".to_string(), &clock);
+            parent = blk_chain.seal_block(parent).unwrap();
+            blk_chain.update_block(parent.clone()).unwrap();
+        }
+
+        blk_chain
+            .blocks()
+            .iter()
+            .map(|block| block.encode())
+            .collect::<Vec<Vec<u8>>>()
+    };
+
+    assert_eq!(build(), build());
+}
+
+#[test]
+fn test_size_limits() {
+    use crate::backend::{Transaction, Transactions};
+
+    let params = ChainParams::regtest();
+    let genesis = Block::genesis(&params);
+    let address = params.genesis_address();
+
+    let block_with = |values: Vec<u64>, to: &str| {
+        let mut transactions = Transactions::empty();
+        for value in values {
+            transactions.values_mut().push(Transaction::new(
+                address.clone(),
+                to.to_string(),
+                value,
+            ));
+        }
+        Block::mine_on(&genesis.header(), transactions, "a".to_string())
+    };
+    // as large as the genesis transaction.
+    let small = block_with(vec![1, 2], &"b".repeat(8));
+    let tx_size = small.transactions().values()[0].size();
+
+    let mut blk_chain =
+        BlockChain::with_params(params.clone().with_size_limits(small.size(), tx_size, 2));
+    blk_chain.update_block(genesis.clone()).unwrap();
+
+    assert!(matches!(
+        blk_chain.update_block(block_with(vec![1, 2, 3], &"b".repeat(8))),
+        Err(BlockValidationError::TooManyTransactions {
+            count: 3,
+            max_block_transactions: 2
+        })
+    ));
+    assert!(matches!(
+        blk_chain.update_block(block_with(vec![1], &"b".repeat(9))),
+        Err(BlockValidationError::TransactionTooLarge { max_tx_size, .. }) if max_tx_size == tx_size
+    ));
+
+    // every transaction fits, the block as a whole doesn't.
+    let mut blk_chain =
+        BlockChain::with_params(params.with_size_limits(small.size() - 1, tx_size, 2));
+    blk_chain.update_block(genesis).unwrap();
+    assert!(matches!(
+        blk_chain.update_block(small),
+        Err(BlockValidationError::BlockTooLarge { .. })
+    ));
+}
+
+#[test]
+fn test_transaction_validity_window() {
+    use crate::backend::functions::address_of;
+    use crate::backend::store::file::funded_chain;
+    use crate::backend::{ManualClock, Mempool, MempoolEvent, TimeLock, Transactions};
+    use crypto::ed25519;
+
+    let params = ChainParams::regtest();
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let mut blk_chain = funded_chain(params.clone(), &[(address_of(&alice_public), 100)]);
+    let genesis = blk_chain.last().unwrap();
+
+    let offer = |nonce: u64, valid_after: Option<TimeLock>, valid_until: Option<TimeLock>| {
+        Transaction::new(address_of(&alice_public), "bob".to_string(), 1)
+            .with_nonce(nonce)
+            .with_validity(valid_after, valid_until)
+            .sign(&alice)
+    };
+    let block_with = |parent: &Block, tx: &Transaction, timestamp: u64| {
+        let mut transactions = Transactions::empty();
+        transactions.values_mut().push(tx.clone());
+        Block::mine_on_at(
+            &parent.header(),
+            transactions,
+            "miner".to_string(),
+            &ManualClock::new(timestamp),
+        )
+    };
+
+    let locked = offer(0, Some(TimeLock::Timestamp(3_000)), None);
+    assert!(matches!(
+        blk_chain.update_block(block_with(&genesis, &locked, 2_000)),
+        Err(BlockValidationError::TransactionNotYetValid)
+    ));
+    let first = blk_chain
+        .seal_block(block_with(&genesis, &locked, 3_000))
+        .unwrap();
+    blk_chain.update_block(first.clone()).unwrap();
+
+    // valid up to and including height 1, and the chain is at 2 now.
+    let lapsed = offer(1, None, Some(TimeLock::Height(1)));
+    assert!(matches!(
+        blk_chain.update_block(block_with(&first, &lapsed, 4_000)),
+        Err(BlockValidationError::TransactionExpired)
+    ));
+
+    // the mempool lets it go once the chain moves past its window.
+    let mut pool = Mempool::new(10, &params);
+    let pending = offer(1, None, Some(TimeLock::Height(2)));
+    let follower = offer(2, None, None);
+    pool.add(pending.clone(), blk_chain.value_store()).unwrap();
+    pool.add(follower.clone(), blk_chain.value_store()).unwrap();
+
+    let empty = Block::mine_on_at(
+        &first.header(),
+        Transactions::empty(),
+        "miner".to_string(),
+        &ManualClock::new(4_000),
+    );
+    let empty = blk_chain.seal_block(empty).unwrap();
+    let update = blk_chain.update_block(empty).unwrap();
+    pool.update(&update, blk_chain.value_store());
+    assert!(pool.is_empty());
+    assert_eq!(
+        pool.take_events(),
+        vec![
+            MempoolEvent::Expired(pending.id()),
+            MempoolEvent::Expired(follower.id())
+        ]
+    );
+}
+
+#[test]
+fn test_mempool_expiry_follows_height() {
+    use crate::backend::functions::address_of;
+    use crate::backend::params::REGTEST_DIFFICULTY;
+    use crate::backend::store::file::funded_chain;
+    use crate::backend::{Mempool, TimeLock, Transactions};
+    use crypto::ed25519;
+
+    let params = ChainParams::regtest();
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let mut blk_chain = funded_chain(params.clone(), &[(address_of(&alice_public), 100)]);
+
+    let mut pool = Mempool::new(10, &params);
+    let pending = Transaction::signed(&alice, "bob".to_string(), 1, 0, 1)
+        .with_validity(None, Some(TimeLock::Height(2)))
+        .sign(&alice);
+    pool.add(pending.clone(), blk_chain.value_store()).unwrap();
+
+    // the index of a block may run ahead of its height, the window goes by the height.
+    let first = Block::mine_with(
+        5,
+        Transactions::empty(),
+        "miner".to_string(),
+        REGTEST_DIFFICULTY,
+    );
+    let first = blk_chain.seal_block(first).unwrap();
+    let update = blk_chain.update_block(first.clone()).unwrap();
+    assert_eq!(update.tip_height(), Some(1));
+    pool.update(&update, blk_chain.value_store());
+    assert!(pool.contains(&pending.id()));
+
+    let second = Block::mine_on(&first.header(), Transactions::empty(), "miner".to_string());
+    let second = blk_chain.seal_block(second).unwrap();
+    let update = blk_chain.update_block(second).unwrap();
+    pool.update(&update, blk_chain.value_store());
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn test_state_root_committed() {
+    use crate::backend::{Encodable, Transactions};
+
+    let mut blk_chain = BlockChain::with_params(ChainParams::regtest());
+    blk_chain
+        .update_block(Block::genesis(&ChainParams::regtest()))
+        .unwrap();
+    let genesis = blk_chain.last_header().unwrap();
+    let block = Block::mine_on(&genesis, Transactions::empty(), "miner".to_string());
+
+    // a block has to commit to the state it leads to.
+    assert!(matches!(
+        blk_chain.update_block(block.clone()),
+        Err(BlockValidationError::MismatchedStateRoot)
+    ));
+    let stale = block
+        .clone()
+        .with_state_root(genesis.state_root().unwrap().clone());
+    assert!(matches!(
+        blk_chain.update_block(stale),
+        Err(BlockValidationError::MismatchedStateRoot)
+    ));
+
+    // the root is part of the hash, so it can't be swapped after mining.
+    let sealed = blk_chain.seal_block(block).unwrap();
+    let mut bytes = sealed.encode();
+    let at = bytes.len() - genesis.state_root().unwrap().len();
+    bytes.splice(at.., genesis.state_root().unwrap().bytes());
+    let tampered = Block::from_bytes(&bytes).unwrap();
+    assert_eq!(tampered.state_root(), genesis.state_root());
+    assert!(matches!(
+        blk_chain.update_block(tampered),
+        Err(BlockValidationError::InvalidHash)
+    ));
+
+    blk_chain.update_block(sealed).unwrap();
+    assert_eq!(
+        blk_chain.last_header().unwrap().state_root(),
+        Some(&blk_chain.value_store().commitment())
+    );
+}
+
+#[test]
+fn test_assumed_valid_skips_signatures() {
+    use crate::backend::functions::address_of;
+    use crate::backend::store::file::funded_genesis;
+    use crate::backend::Transactions;
+    use crypto::ed25519;
+
+    let params = ChainParams::regtest();
+    let (_, alice_public) = ed25519::keypair(&[1; 32]);
+    let (mallory, _) = ed25519::keypair(&[2; 32]);
+    let genesis = funded_genesis(&params, &[(address_of(&alice_public), 100)]);
+
+    // signed by the wrong key, which only a chain trusting the block can take.
+    let forged = Transaction::new(address_of(&alice_public), "bob".to_string(), 10).sign(&mallory);
+    let mut transactions = Transactions::empty();
+    transactions.values_mut().push(forged);
+
+    let mut source = BlockChain::with_params(params.clone().with_assumed_valid(5, "x".to_string()));
+    source.update_block(genesis.clone()).unwrap();
+    let first = Block::mine_on(&genesis.header(), transactions, "miner".to_string());
+    let first = source.seal_block(first).unwrap();
+    source.update_block(first.clone()).unwrap();
+    let second = Block::mine_on(&first.header(), Transactions::empty(), "miner".to_string());
+    let second = source.seal_block(second).unwrap();
+
+    let mut blk_chain = BlockChain::with_params(
+        params
+            .clone()
+            .with_assumed_valid(2, second.current_hash_str()),
+    );
+    blk_chain.update_block(genesis.clone()).unwrap();
+    blk_chain.update_block(first.clone()).unwrap();
+    blk_chain.update_block(second.clone()).unwrap();
+    assert_eq!(
+        blk_chain.value_store().balance(&"bob".to_string()),
+        Some(10)
+    );
+
+    let mut strict = BlockChain::with_params(params.clone());
+    strict.update_block(genesis.clone()).unwrap();
+    assert!(matches!(
+        strict.update_block(first.clone()),
+        Err(BlockValidationError::InvalidSignature)
+    ));
+
+    // the assumed valid block has to be on the chain, like a checkpoint.
+    let mut other = BlockChain::with_params(params.with_assumed_valid(2, "x".to_string()));
+    other.update_block(genesis).unwrap();
+    other.update_block(first).unwrap();
+    assert!(matches!(
+        other.update_block(second),
+        Err(BlockValidationError::CheckpointMismatch)
+    ));
+}
+
+#[test]
+fn test_rejected_transaction_error() {
+    use crate::backend::functions::address_of;
+    use crate::backend::store::file::funded_chain;
+    use crate::backend::Transactions;
+    use crypto::ed25519;
+
+    let params = ChainParams::regtest();
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let mut blk_chain = funded_chain(params, &[(address_of(&alice_public), 100)]);
+    let genesis = blk_chain.last_header().unwrap();
+
+    // the block is refused with the error of the transaction which failed.
+    let overdrawn =
+        Transaction::new(address_of(&alice_public), "bob".to_string(), 200).sign(&alice);
+    let mut transactions = Transactions::empty();
+    transactions.values_mut().push(overdrawn);
+    let block = Block::mine_on(&genesis, transactions, "miner".to_string());
+    assert!(matches!(
+        blk_chain.update_block(block),
+        Err(BlockValidationError::InsufficientInputVal)
+    ));
+}
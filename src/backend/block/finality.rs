@@ -0,0 +1,185 @@
+use std::collections::{HashMap, HashSet};
+
+use crypto::ed25519;
+
+use crate::backend::{
+    enums::BlockValidationError,
+    functions::{hash_str_bytes, hex_bytes, hex_str},
+    params::Finality,
+    types::Hash,
+};
+
+// a validator's signed statement that the block at `height` is final.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vote {
+    height: u64,
+    block_hash: Hash,
+    // hex ed25519 public key.
+    validator: Hash,
+    signature: Hash,
+}
+
+impl Vote {
+    // sign with a secret key as returned by `ed25519::keypair`.
+    pub fn sign(height: u64, block_hash: Hash, secret_key: &[u8; 64]) -> Vote {
+        let signature = ed25519::signature(&vote_message(height, &block_hash), secret_key);
+
+        Vote {
+            height,
+            block_hash,
+            // the public half sits at the end of the secret key.
+            validator: hex_str(&secret_key[32..]),
+            signature: hex_str(&signature),
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn block_hash(&self) -> &Hash {
+        &self.block_hash
+    }
+
+    pub fn validator(&self) -> &Hash {
+        &self.validator
+    }
+
+    pub fn verify(&self) -> bool {
+        let (public_key, signature) = match (hex_bytes(&self.validator), hex_bytes(&self.signature))
+        {
+            (Some(public_key), Some(signature)) => (public_key, signature),
+            _ => return false,
+        };
+
+        if public_key.len() != 32 || signature.len() != 64 {
+            return false;
+        }
+
+        ed25519::verify(
+            &vote_message(self.height, &self.block_hash),
+            &public_key,
+            &signature,
+        )
+    }
+}
+
+fn vote_message(height: u64, block_hash: &Hash) -> Vec<u8> {
+    let mut message = b"vote/".to_vec();
+    message.extend(height.to_le_bytes());
+    message.extend(hash_str_bytes(block_hash));
+    message
+}
+
+// finalized height of the active chain, and the votes collected so far.
+#[derive(Debug, Default)]
+pub struct FinalityTracker {
+    finalized: Option<u64>,
+    // validators which voted, by block hash and height.
+    votes: HashMap<(u64, Hash), HashSet<Hash>>,
+}
+
+impl FinalityTracker {
+    pub fn new() -> Self {
+        FinalityTracker {
+            finalized: None,
+            votes: HashMap::new(),
+        }
+    }
+
+    pub fn finalized(&self) -> Option<u64> {
+        self.finalized
+    }
+
+    // finality only ever moves forward.
+    pub fn finalize(&mut self, height: u64) {
+        if self.finalized.is_none_or(|finalized| finalized < height) {
+            self.finalized = Some(height);
+        }
+
+        let finalized = self.finalized;
+        self.votes
+            .retain(|(height, _), _| finalized.is_none_or(|finalized| *height > finalized));
+    }
+
+    // keep a vote of one of the validators of `rule`.
+    pub fn add_vote(&mut self, vote: Vote, rule: &Finality) -> Result<(), BlockValidationError> {
+        let validators = match rule {
+            Finality::Votes { validators, .. } => validators,
+            _ => return Err(BlockValidationError::InvalidVote),
+        };
+
+        if !validators.contains(vote.validator()) || !vote.verify() {
+            return Err(BlockValidationError::InvalidVote);
+        }
+
+        // votes for final heights don't matter anymore.
+        if self
+            .finalized
+            .is_some_and(|finalized| vote.height() <= finalized)
+        {
+            return Ok(());
+        }
+
+        self.votes
+            .entry((vote.height, vote.block_hash))
+            .or_default()
+            .insert(vote.validator);
+
+        Ok(())
+    }
+
+    // blocks with enough votes under `rule`, highest first.
+    pub fn voted_blocks(&self, rule: &Finality) -> Vec<(u64, Hash)> {
+        let threshold = match rule {
+            Finality::Votes { threshold, .. } => *threshold,
+            _ => return vec![],
+        };
+
+        let mut blocks = self
+            .votes
+            .iter()
+            .filter(|(_, validators)| validators.len() >= threshold)
+            .map(|(block, _)| block.clone())
+            .collect::<Vec<(u64, Hash)>>();
+        blocks.sort_by(|a, b| b.cmp(a));
+
+        blocks
+    }
+}
+
+#[test]
+fn test_vote_threshold() {
+    let keys = (0..3_u8)
+        .map(|seed| ed25519::keypair(&[seed; 32]))
+        .collect::<Vec<([u8; 64], [u8; 32])>>();
+    let rule = Finality::Votes {
+        validators: keys.iter().map(|(_, public)| hex_str(public)).collect(),
+        threshold: 2,
+    };
+    let block_hash = "ab".repeat(32);
+
+    let mut tracker = FinalityTracker::new();
+    tracker
+        .add_vote(Vote::sign(4, block_hash.clone(), &keys[0].0), &rule)
+        .unwrap();
+    assert!(tracker.voted_blocks(&rule).is_empty());
+
+    // a vote altered after signing, or by someone outside the set, is refused.
+    let mut forged = Vote::sign(4, block_hash.clone(), &keys[1].0);
+    forged.height = 5;
+    assert!(tracker.add_vote(forged, &rule).is_err());
+    let outsider = ed25519::keypair(&[9; 32]).0;
+    assert!(tracker
+        .add_vote(Vote::sign(4, block_hash.clone(), &outsider), &rule)
+        .is_err());
+
+    tracker
+        .add_vote(Vote::sign(4, block_hash.clone(), &keys[1].0), &rule)
+        .unwrap();
+    assert_eq!(tracker.voted_blocks(&rule), vec![(4, block_hash)]);
+
+    tracker.finalize(4);
+    assert_eq!(tracker.finalized(), Some(4));
+    assert!(tracker.voted_blocks(&rule).is_empty());
+}
@@ -0,0 +1,168 @@
+use crate::backend::{
+    enums::DecodeError,
+    functions::*,
+    traits::encodable::{put_opt_str, put_str, ByteReader},
+    types::Hash,
+    Encodable, Hashable,
+};
+
+// everything about a block except its transactions.
+// enough to check proof of work and how blocks link up, even once bodies are pruned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    index: u64,
+    timestamp: u64,
+    prev: Option<Hash>,
+    nonce: u64,
+    difficulty: u128,
+    tx_root: Hash,
+    hash: Hash,
+    broadcaster_addr: Hash,
+    // commitment to the values after this block was applied. part of the block hash,
+    // so the miner vouches for it.
+    state_root: Option<Hash>,
+}
+
+impl BlockHeader {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        index: u64,
+        timestamp: u64,
+        prev: Option<Hash>,
+        nonce: u64,
+        difficulty: u128,
+        tx_root: Hash,
+        hash: Hash,
+        broadcaster_addr: Hash,
+        state_root: Option<Hash>,
+    ) -> BlockHeader {
+        BlockHeader {
+            index,
+            timestamp,
+            prev,
+            nonce,
+            difficulty,
+            tx_root,
+            hash,
+            broadcaster_addr,
+            state_root,
+        }
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn difficulty(&self) -> u128 {
+        self.difficulty
+    }
+
+    pub fn tx_root(&self) -> &Hash {
+        &self.tx_root
+    }
+
+    pub fn current_hash(&self) -> &Hash {
+        &self.hash
+    }
+
+    pub fn current_hash_str(&self) -> Hash {
+        self.hash.clone()
+    }
+
+    pub fn current_hash_bytes(&self) -> [u8; 32] {
+        hash_str_bytes(&self.hash)
+    }
+
+    pub fn broadcaster(&self) -> &Hash {
+        &self.broadcaster_addr
+    }
+
+    pub fn prev_hash_str(&self) -> Hash {
+        self.prev.clone().unwrap_or_default()
+    }
+
+    pub fn state_root(&self) -> Option<&Hash> {
+        self.state_root.as_ref()
+    }
+}
+
+// the bytes a block hash is computed from. shared by blocks and their headers.
+pub fn header_bytes(
+    index: u64,
+    timestamp: u64,
+    prev: &Option<Hash>,
+    nonce: u64,
+    tx_root: &Hash,
+    difficulty: u128,
+    state_root: &Option<Hash>,
+) -> Vec<u8> {
+    let mut result = vec![];
+
+    result.extend(u64_bytes(&index));
+    result.extend(u64_bytes(&timestamp));
+
+    //put if prev exists.
+    if let Some(prev) = prev {
+        result.extend(prev.as_bytes());
+    }
+
+    result.extend(u64_bytes(&nonce));
+    result.extend(hash_str_bytes(tx_root));
+    result.extend(u128_bytes(&difficulty));
+
+    if let Some(state_root) = state_root {
+        result.extend(hash_str_bytes(state_root));
+    }
+
+    result
+}
+
+impl Hashable for BlockHeader {
+    fn bytes(&self) -> Vec<u8> {
+        header_bytes(
+            self.index,
+            self.timestamp,
+            &self.prev,
+            self.nonce,
+            &self.tx_root,
+            self.difficulty,
+            &self.state_root,
+        )
+    }
+}
+
+impl Encodable for BlockHeader {
+    fn encode(&self) -> Vec<u8> {
+        let mut result = vec![];
+
+        result.extend(u64_bytes(&self.index));
+        result.extend(u64_bytes(&self.timestamp));
+        put_opt_str(&mut result, &self.prev);
+        result.extend(u64_bytes(&self.nonce));
+        result.extend(u128_bytes(&self.difficulty));
+        put_str(&mut result, &self.tx_root);
+        put_str(&mut result, &self.hash);
+        put_str(&mut result, &self.broadcaster_addr);
+        put_opt_str(&mut result, &self.state_root);
+
+        result
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        Ok(BlockHeader {
+            index: reader.u64()?,
+            timestamp: reader.u64()?,
+            prev: reader.opt_string()?,
+            nonce: reader.u64()?,
+            difficulty: reader.u128()?,
+            tx_root: reader.string()?,
+            hash: reader.string()?,
+            broadcaster_addr: reader.string()?,
+            state_root: reader.opt_string()?,
+        })
+    }
+}
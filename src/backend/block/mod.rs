@@ -1,2 +1,8 @@
+#[allow(clippy::module_inception)]
 pub mod block;
-pub mod blockchain;
\ No newline at end of file
+pub mod blockchain;
+pub mod finality;
+pub mod header;
+pub mod orphan;
+pub mod template;
+pub mod tree;
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use crate::backend::{types::Hash, Block};
+
+// how many orphans are kept by default.
+pub const DEFAULT_MAX_ORPHANS: usize = 100;
+// how long an orphan waits for its parent by default, in nanoseconds. 20 minutes.
+pub const DEFAULT_ORPHAN_AGE: u64 = 20 * 60 * 1_000_000_000;
+
+#[derive(Debug, Clone)]
+struct Orphan {
+    block: Block,
+    received: u64,
+}
+
+// blocks which arrived before their parent, keyed by the missing parent hash.
+#[derive(Debug)]
+pub struct OrphanPool {
+    by_parent: HashMap<Hash, Vec<Orphan>>,
+    max_size: usize,
+    max_age: u64,
+}
+
+impl Default for OrphanPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ORPHANS, DEFAULT_ORPHAN_AGE)
+    }
+}
+
+impl OrphanPool {
+    pub fn new(max_size: usize, max_age: u64) -> Self {
+        OrphanPool {
+            by_parent: HashMap::new(),
+            max_size,
+            max_age,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_parent.values().map(|orphans| orphans.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_parent.is_empty()
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.by_parent
+            .values()
+            .flatten()
+            .any(|orphan| orphan.block.current_hash() == hash)
+    }
+
+    // parents which have to be fetched before the pool can drain.
+    // parents which are orphans themselves are left out, their own parent is listed instead.
+    pub fn missing_parents(&self) -> Vec<Hash> {
+        let mut parents = self
+            .by_parent
+            .keys()
+            .filter(|parent| !self.contains(parent))
+            .cloned()
+            .collect::<Vec<Hash>>();
+        parents.sort();
+
+        parents
+    }
+
+    // keep a block until its parent shows up. the oldest orphan makes room when full.
+    // blocks without a parent hash can't be orphans and are ignored.
+    pub fn insert(&mut self, block: Block, now: u64) {
+        let parent = match block.prev() {
+            Some(parent) => parent.clone(),
+            None => return,
+        };
+
+        if self.max_size == 0 || self.contains(block.current_hash()) {
+            return;
+        }
+
+        self.expire(now);
+        while self.len() >= self.max_size {
+            self.remove_oldest();
+        }
+
+        self.by_parent.entry(parent).or_default().push(Orphan {
+            block,
+            received: now,
+        });
+    }
+
+    // take every orphan waiting for `parent`.
+    pub fn take_children(&mut self, parent: &Hash) -> Vec<Block> {
+        self.by_parent
+            .remove(parent)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|orphan| orphan.block)
+            .collect()
+    }
+
+    // drop orphans which waited longer than the age limit.
+    pub fn expire(&mut self, now: u64) {
+        let max_age = self.max_age;
+
+        for orphans in self.by_parent.values_mut() {
+            orphans.retain(|orphan| now.saturating_sub(orphan.received) <= max_age);
+        }
+        self.by_parent.retain(|_, orphans| !orphans.is_empty());
+    }
+
+    fn remove_oldest(&mut self) {
+        let oldest = self
+            .by_parent
+            .iter()
+            .flat_map(|(parent, orphans)| {
+                orphans
+                    .iter()
+                    .enumerate()
+                    .map(move |(idx, orphan)| (orphan.received, parent.clone(), idx))
+            })
+            .min();
+
+        if let Some((_, parent, idx)) = oldest {
+            let orphans = self.by_parent.get_mut(&parent).unwrap();
+            orphans.remove(idx);
+
+            if orphans.is_empty() {
+                self.by_parent.remove(&parent);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_orphan_pool_limits() {
+    use crate::backend::{ChainParams, Transactions};
+
+    let genesis = Block::genesis(&ChainParams::regtest());
+    let first = Block::mine_on(&genesis.header(), Transactions::empty(), "a".to_string());
+    let second = Block::mine_on(&first.header(), Transactions::empty(), "a".to_string());
+    let other = Block::mine_on(&genesis.header(), Transactions::empty(), "b".to_string());
+
+    let mut pool = OrphanPool::new(2, 100);
+    pool.insert(second.clone(), 10);
+    pool.insert(first.clone(), 20);
+    assert_eq!(pool.missing_parents(), vec![genesis.current_hash_str()]);
+
+    // full, so the oldest orphan goes.
+    pool.insert(other, 30);
+    assert_eq!(pool.len(), 2);
+    assert!(!pool.contains(second.current_hash()));
+
+    // the first orphan is past the age limit by now.
+    pool.expire(125);
+    assert_eq!(pool.len(), 1);
+    assert!(!pool.contains(first.current_hash()));
+
+    assert_eq!(pool.take_children(&genesis.current_hash_str()).len(), 1);
+    assert!(pool.is_empty());
+}
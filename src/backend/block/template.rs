@@ -0,0 +1,282 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::backend::{
+    enums::BlockValidationError, store::chainstore::ChainStore, types::Hash, Block, BlockChain,
+    BlockHeader, Mempool, Transaction, Transactions,
+};
+
+// hash of a mined block, as hex.
+const HASH_LEN: usize = 64;
+
+// everything needed to mine the next block on top of the tip of a chain.
+#[derive(Debug, Clone)]
+pub struct BlockTemplate {
+    parent: BlockHeader,
+    // of the block in the chain, which may differ from its index.
+    height: u64,
+    timestamp: u64,
+    transactions: Transactions,
+    // receives the block reward and the fees.
+    coinbase: Hash,
+    // of the values after the block, which the block hash commits to.
+    state_root: Hash,
+}
+
+impl BlockTemplate {
+    // fill a block with the best paying transactions of `mempool` the limits of the chain allow.
+    // a sender's transactions go in by nonce, and one that doesn't fit, or fails on top of
+    // the ones before it, holds back the rest.
+    pub fn new<S: ChainStore>(
+        chain: &mut BlockChain<S>,
+        mempool: &Mempool,
+        coinbase: Hash,
+    ) -> Result<Self, BlockValidationError> {
+        let parent = chain
+            .last_header()
+            .ok_or(BlockValidationError::UnknownParent)?;
+
+        // the clock may lag behind the chain, but the block has to be later than its median.
+        let timestamp = chain
+            .clock()
+            .now()
+            .max(chain.median_time_past(&parent)? + 1);
+
+        let mut template = BlockTemplate {
+            parent,
+            height: chain.get_block_size() as u64,
+            timestamp,
+            transactions: Transactions::empty(),
+            coinbase,
+            state_root: String::new(),
+        };
+
+        // fee rate rank of every transaction, and what each sender has pending in nonce order.
+        let ranked = mempool.transactions();
+        let rank = ranked
+            .iter()
+            .enumerate()
+            .map(|(rank, tx)| (tx.id(), rank))
+            .collect::<HashMap<Hash, usize>>();
+        let mut queues = HashMap::<&Hash, VecDeque<&Transaction>>::new();
+        for tx in ranked.iter() {
+            queues
+                .entry(tx.from_address())
+                .or_insert_with(|| mempool.transactions_of(tx.from_address()).into());
+        }
+
+        let params = chain.params().clone();
+        let mut size = template.size();
+
+        while template.transactions.values().len() < params.max_block_transactions() {
+            // the best paying transaction any sender can include next.
+            let next = queues
+                .iter()
+                .filter_map(|(sender, queue)| queue.front().map(|tx| (*sender, *tx)))
+                .min_by_key(|(_, tx)| rank[&tx.id()]);
+
+            let (sender, tx) = match next {
+                Some(next) => next,
+                None => break,
+            };
+
+            // every transaction is tried on the state the ones before it leave.
+            if tx.size() > params.max_tx_size()
+                || size + tx.size() > params.max_block_size()
+                || chain
+                    .apply_block_transaction(tx, &template.coinbase, template.height, timestamp)
+                    .is_err()
+            {
+                queues.remove(sender);
+                continue;
+            }
+
+            size += tx.size();
+            template.transactions.values_mut().push(tx.clone());
+            queues.get_mut(sender).unwrap().pop_front();
+        }
+        chain.discard_changes();
+
+        let block = Block::new(
+            template.index(),
+            Some(template.parent.current_hash_str()),
+            template.transactions.clone(),
+            template.coinbase.clone(),
+            template.difficulty(),
+            timestamp,
+        );
+        template.state_root = chain.state_root_after(template.parent.current_hash(), &block)?;
+
+        Ok(template)
+    }
+
+    pub fn parent(&self) -> &BlockHeader {
+        &self.parent
+    }
+
+    // index of the mined block, which follows the one of its parent.
+    pub fn index(&self) -> u64 {
+        self.parent.index() + 1
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn difficulty(&self) -> u128 {
+        self.parent.difficulty()
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn transactions(&self) -> &Transactions {
+        &self.transactions
+    }
+
+    pub fn coinbase(&self) -> &Hash {
+        &self.coinbase
+    }
+
+    pub fn state_root(&self) -> &Hash {
+        &self.state_root
+    }
+
+    // serialized size of the mined block, as it is checked against the size limit.
+    pub fn size(&self) -> usize {
+        let fixed = 8 + 8 + 8 + 16;
+        let prev = 1 + 4 + self.parent.current_hash_str().len();
+        let transactions = 4 + self
+            .transactions
+            .values()
+            .iter()
+            .map(|tx| tx.size())
+            .sum::<usize>();
+        let hash = 4 + HASH_LEN;
+        let coinbase = 4 + self.coinbase.len();
+        let state_root = 1 + 4 + HASH_LEN;
+
+        fixed + prev + transactions + hash + coinbase + state_root
+    }
+
+    pub fn mine(&self) -> Block {
+        Block::mine_template(self)
+    }
+}
+
+#[test]
+fn test_template_fills_block() {
+    use crate::backend::functions::address_of;
+    use crate::backend::store::file::funded_genesis;
+    use crate::backend::ChainParams;
+    use crypto::ed25519;
+
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let (bob, bob_public) = ed25519::keypair(&[2; 32]);
+
+    let params = ChainParams::regtest();
+    let genesis = funded_genesis(
+        &params,
+        &[
+            (address_of(&alice_public), 100),
+            (address_of(&bob_public), 100),
+        ],
+    );
+
+    // room for three of the four transactions.
+    let tx_size = Transaction::signed(&alice, "c".to_string(), 1, 0, 1).size();
+    let empty_size = BlockTemplate {
+        parent: genesis.header(),
+        height: 1,
+        timestamp: 0,
+        transactions: Transactions::empty(),
+        coinbase: "miner".to_string(),
+        state_root: String::new(),
+    }
+    .size();
+    let params = params.with_size_limits(empty_size + 3 * tx_size, tx_size, 10);
+    let mut blk_chain = BlockChain::with_params(params.clone());
+    blk_chain.update_block(genesis).unwrap();
+
+    // alice's second transaction pays the most, but has to wait for her first one.
+    let mut pool = Mempool::new(10, &params);
+    let alice_first = Transaction::signed(&alice, "c".to_string(), 1, 0, 1);
+    let alice_second = Transaction::signed(&alice, "c".to_string(), 1, 1, 9);
+    let bob_first = Transaction::signed(&bob, "c".to_string(), 1, 0, 5);
+    let bob_second = Transaction::signed(&bob, "c".to_string(), 1, 1, 2);
+    for tx in [&alice_first, &alice_second, &bob_first, &bob_second] {
+        pool.add(tx.clone(), blk_chain.value_store()).unwrap();
+    }
+
+    let template = BlockTemplate::new(&mut blk_chain, &pool, "miner".to_string()).unwrap();
+    assert_eq!(
+        template
+            .transactions()
+            .values()
+            .iter()
+            .map(|tx| tx.id())
+            .collect::<Vec<Hash>>(),
+        vec![bob_first.id(), bob_second.id(), alice_first.id()]
+    );
+
+    let block = template.mine();
+    assert_eq!(block.size(), template.size());
+    assert_eq!(block.index(), 1);
+
+    blk_chain.update_block(block).unwrap();
+    assert_eq!(
+        blk_chain.value_store().balance(&"miner".to_string()),
+        Some(1 + 5 + 2 + 1)
+    );
+}
+
+#[test]
+fn test_template_skips_failing_transactions() {
+    use crate::backend::functions::address_of;
+    use crate::backend::params::REGTEST_DIFFICULTY;
+    use crate::backend::store::file::funded_chain;
+    use crate::backend::{ChainParams, TimeLock};
+    use crypto::ed25519;
+
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let (carol, carol_public) = ed25519::keypair(&[3; 32]);
+
+    let params = ChainParams::regtest();
+    let mut blk_chain = funded_chain(params.clone(), &[(address_of(&alice_public), 100)]);
+    // carol only has funds on another chain, where her transaction got into the pool.
+    let elsewhere = funded_chain(params.clone(), &[(address_of(&carol_public), 100)]);
+
+    // the index of a block may run ahead of its height.
+    let first = Block::mine_with(
+        5,
+        Transactions::empty(),
+        "miner".to_string(),
+        REGTEST_DIFFICULTY,
+    );
+    let first = blk_chain.seal_block(first).unwrap();
+    blk_chain.update_block(first).unwrap();
+
+    let mut pool = Mempool::new(10, &params);
+    let stranded = Transaction::signed(&carol, "c".to_string(), 1, 0, 9);
+    let expiring = Transaction::signed(&alice, "c".to_string(), 1, 0, 1)
+        .with_validity(None, Some(TimeLock::Height(2)))
+        .sign(&alice);
+    pool.add(stranded, elsewhere.value_store()).unwrap();
+    pool.add(expiring.clone(), blk_chain.value_store()).unwrap();
+
+    let template = BlockTemplate::new(&mut blk_chain, &pool, "miner".to_string()).unwrap();
+    assert_eq!(template.height(), 2);
+    assert_eq!(template.index(), 6);
+    assert_eq!(
+        template
+            .transactions()
+            .values()
+            .iter()
+            .map(|tx| tx.id())
+            .collect::<Vec<Hash>>(),
+        vec![expiring.id()]
+    );
+
+    blk_chain.update_block(template.mine()).unwrap();
+    assert_eq!(blk_chain.value_store().balance(&"c".to_string()), Some(1));
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use crate::backend::{
+    types::{Hash, StateWrite},
+    Block,
+};
+
+// work a block of the given difficulty stands for. a lower target means more work.
+pub fn block_work(difficulty: u128) -> u128 {
+    u128::MAX / difficulty.max(1)
+}
+
+// a known block which is not on the active chain.
+#[derive(Debug, Clone)]
+pub struct TreeNode {
+    block: Block,
+    height: u64,
+    // cumulative work from genesis up to and including this block.
+    work: u128,
+}
+
+impl TreeNode {
+    pub fn new(block: Block, height: u64, work: u128) -> Self {
+        TreeNode {
+            block,
+            height,
+            work,
+        }
+    }
+
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn work(&self) -> u128 {
+        self.work
+    }
+}
+
+// competing branches next to the active chain kept by the store.
+// the active chain itself is only tracked by its cumulative work and undo data.
+#[derive(Debug, Default)]
+pub struct BlockTree {
+    active_work: Vec<u128>,
+    side: HashMap<Hash, TreeNode>,
+    // state writes reverting an active block, by block hash.
+    undo: HashMap<Hash, Vec<StateWrite>>,
+}
+
+impl BlockTree {
+    pub fn new() -> Self {
+        BlockTree {
+            active_work: vec![],
+            side: HashMap::new(),
+            undo: HashMap::new(),
+        }
+    }
+
+    // cumulative work of the active chain at `height`.
+    pub fn active_work(&self, height: u64) -> Option<u128> {
+        self.active_work.get(height as usize).copied()
+    }
+
+    pub fn tip_work(&self) -> u128 {
+        self.active_work.last().copied().unwrap_or(0)
+    }
+
+    // record the active block at `height`, dropping the work of any block above it.
+    pub fn set_active(&mut self, height: u64, difficulty: u128) {
+        self.active_work.truncate(height as usize);
+        let work = self.tip_work().saturating_add(block_work(difficulty));
+        self.active_work.push(work);
+    }
+
+    pub fn side_block(&self, hash: &Hash) -> Option<&TreeNode> {
+        self.side.get(hash)
+    }
+
+    pub fn insert_side(&mut self, node: TreeNode) {
+        self.side.insert(node.block.current_hash_str(), node);
+    }
+
+    pub fn remove_side(&mut self, hash: &Hash) -> Option<TreeNode> {
+        self.side.remove(hash)
+    }
+
+    // drop a side block together with every side block built on it.
+    pub fn remove_side_branch(&mut self, hash: &Hash) {
+        let mut pending = vec![hash.clone()];
+
+        while let Some(hash) = pending.pop() {
+            self.side.remove(&hash);
+            pending.extend(
+                self.side
+                    .iter()
+                    .filter(|(_, node)| node.block.prev() == Some(&hash))
+                    .map(|(child, _)| child.clone()),
+            );
+        }
+    }
+
+    // drop side blocks at or below `height`, they can no longer become active.
+    pub fn remove_side_up_to(&mut self, height: u64) {
+        self.side.retain(|_, node| node.height > height);
+    }
+
+    // side blocks from the one branching off the active chain up to `hash`.
+    pub fn branch(&self, hash: &Hash) -> Vec<Hash> {
+        let mut branch = vec![];
+        let mut current = self.side.get(hash);
+
+        while let Some(node) = current {
+            branch.push(node.block.current_hash_str());
+            current = node.block.prev().and_then(|prev| self.side.get(prev));
+        }
+        branch.reverse();
+
+        branch
+    }
+
+    pub fn undo(&self, hash: &Hash) -> Option<&Vec<StateWrite>> {
+        self.undo.get(hash)
+    }
+
+    pub fn set_undo(&mut self, hash: Hash, writes: Vec<StateWrite>) {
+        self.undo.insert(hash, writes);
+    }
+
+    pub fn remove_undo(&mut self, hash: &Hash) {
+        self.undo.remove(hash);
+    }
+}
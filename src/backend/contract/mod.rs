@@ -0,0 +1,2 @@
+pub mod receipt;
+pub mod vm;
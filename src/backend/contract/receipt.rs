@@ -0,0 +1,76 @@
+use crate::backend::{enums::VmError, types::Hash};
+
+// words a contract emitted while it ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Log {
+    contract: Hash,
+    data: Vec<u64>,
+}
+
+impl Log {
+    pub fn new(contract: Hash, data: Vec<u64>) -> Self {
+        Log { contract, data }
+    }
+
+    pub fn contract(&self) -> &Hash {
+        &self.contract
+    }
+
+    pub fn data(&self) -> &Vec<u64> {
+        &self.data
+    }
+}
+
+// outcome of a deploy or call transaction. a failed one keeps no changes and no logs,
+// but its gas is paid all the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    tx_id: Hash,
+    contract: Hash,
+    gas_used: u64,
+    // the word on top of the stack when the contract stopped.
+    result: Result<Option<u64>, VmError>,
+    logs: Vec<Log>,
+}
+
+impl Receipt {
+    pub fn new(
+        tx_id: Hash,
+        contract: Hash,
+        gas_used: u64,
+        result: Result<Option<u64>, VmError>,
+        logs: Vec<Log>,
+    ) -> Self {
+        Receipt {
+            tx_id,
+            contract,
+            gas_used,
+            result,
+            logs,
+        }
+    }
+
+    pub fn tx_id(&self) -> &Hash {
+        &self.tx_id
+    }
+
+    pub fn contract(&self) -> &Hash {
+        &self.contract
+    }
+
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    pub fn result(&self) -> &Result<Option<u64>, VmError> {
+        &self.result
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    pub fn logs(&self) -> &Vec<Log> {
+        &self.logs
+    }
+}
@@ -0,0 +1,590 @@
+use crypto::{digest::Digest, sha2::Sha256};
+
+use crate::backend::{
+    enums::{DecodeError, VmError},
+    traits::encodable::ByteReader,
+    types::Hash,
+    Encodable,
+};
+
+use super::receipt::{Log, Receipt};
+
+// most words the stack may hold.
+pub const MAX_STACK_DEPTH: usize = 1024;
+// paid by every deploy and call before any code runs.
+pub const BASE_GAS: u64 = 10;
+// paid per instruction of deployed code.
+pub const DEPLOY_GAS_PER_INSTR: u64 = 2;
+
+// one instruction of contract code. the machine works on u64 words and has no access
+// to anything but the storage and balance of the contract, so runs are deterministic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instr {
+    Push(u64),
+    Pop,
+    Dup,
+    Swap,
+    // copy the second word to the top.
+    Over,
+    // arithmetic takes the second word as the left operand, and fails on overflow.
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    // comparisons push 1 or 0.
+    Eq,
+    Lt,
+    Gt,
+    Not,
+    // continue at an instruction index. jumping to the end stops.
+    Jump(u32),
+    // pops a word and jumps unless it is 0.
+    JumpIf(u32),
+    // pops an index and pushes that argument of the call.
+    Arg,
+    // `address_word` of the caller.
+    Caller,
+    // value sent along with the call.
+    Value,
+    Height,
+    // balance of the contract.
+    Balance,
+    // pops a slot and pushes the word stored there, 0 if none is.
+    Load,
+    // pops a slot, then the word to store at it.
+    Store,
+    // pops this many words into a log, in the order they were pushed.
+    Log(u8),
+    // pops an amount and pays it from the contract to the caller.
+    Pay,
+    Stop,
+    // fail the call, undoing everything it did.
+    Revert,
+}
+
+impl Instr {
+    fn gas(&self) -> u64 {
+        match self {
+            Instr::Load | Instr::Balance => 5,
+            Instr::Log(_) => 10,
+            Instr::Store | Instr::Pay => 20,
+            _ => 1,
+        }
+    }
+
+    fn code(&self) -> u8 {
+        match self {
+            Instr::Push(_) => 0x00,
+            Instr::Pop => 0x01,
+            Instr::Dup => 0x02,
+            Instr::Swap => 0x03,
+            Instr::Over => 0x04,
+            Instr::Add => 0x10,
+            Instr::Sub => 0x11,
+            Instr::Mul => 0x12,
+            Instr::Div => 0x13,
+            Instr::Mod => 0x14,
+            Instr::Eq => 0x20,
+            Instr::Lt => 0x21,
+            Instr::Gt => 0x22,
+            Instr::Not => 0x23,
+            Instr::Jump(_) => 0x30,
+            Instr::JumpIf(_) => 0x31,
+            Instr::Arg => 0x40,
+            Instr::Caller => 0x41,
+            Instr::Value => 0x42,
+            Instr::Height => 0x43,
+            Instr::Balance => 0x44,
+            Instr::Load => 0x50,
+            Instr::Store => 0x51,
+            Instr::Log(_) => 0x60,
+            Instr::Pay => 0x61,
+            Instr::Stop => 0x70,
+            Instr::Revert => 0x71,
+        }
+    }
+}
+
+// code of a contract.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    code: Vec<Instr>,
+}
+
+impl Program {
+    pub fn new(code: Vec<Instr>) -> Self {
+        Program { code }
+    }
+
+    pub fn code(&self) -> &Vec<Instr> {
+        &self.code
+    }
+}
+
+// the state a contract runs against.
+pub trait ContractState {
+    fn code(&self, contract: &Hash) -> Option<Program>;
+
+    fn set_code(&mut self, contract: &Hash, program: Program);
+
+    fn load(&self, contract: &Hash, slot: u64) -> u64;
+
+    fn store(&mut self, contract: &Hash, slot: u64, word: u64);
+
+    fn balance_of(&self, address: &Hash) -> u64;
+
+    // move `amount` between two addresses. false, and nothing moved, if `from` can't cover it.
+    fn pay(&mut self, from: &Hash, to: &Hash, amount: u64) -> bool;
+}
+
+// who runs a contract, and with what.
+#[derive(Debug, Clone)]
+pub struct CallContext {
+    contract: Hash,
+    caller: Hash,
+    value: u64,
+    args: Vec<u64>,
+    height: u64,
+}
+
+impl CallContext {
+    pub fn new(contract: Hash, caller: Hash, value: u64, args: Vec<u64>, height: u64) -> Self {
+        CallContext {
+            contract,
+            caller,
+            value,
+            args,
+            height,
+        }
+    }
+}
+
+// address the contract deployed by the transaction `tx_id` lives at.
+pub fn contract_address(tx_id: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.input(b"contract/");
+    hasher.input(tx_id.as_bytes());
+
+    hasher.result_str()
+}
+
+// an address as a machine word, to compare the caller against.
+pub fn address_word(address: &str) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.input(address.as_bytes());
+    let mut hash = [0_u8; 32];
+    hasher.result(&mut hash);
+
+    let mut word = [0_u8; 8];
+    word.copy_from_slice(&hash[..8]);
+    u64::from_le_bytes(word)
+}
+
+// store `program` at the contract address of `tx_id`, and give it the value of the context.
+// the caller has to undo the changes of a failed deploy.
+pub fn deploy(
+    tx_id: Hash,
+    context: &CallContext,
+    program: &Program,
+    state: &mut dyn ContractState,
+    gas_limit: u64,
+) -> Receipt {
+    let gas = (program.code.len() as u64)
+        .saturating_mul(DEPLOY_GAS_PER_INSTR)
+        .saturating_add(BASE_GAS);
+
+    let result = if gas > gas_limit {
+        Err(VmError::OutOfGas)
+    } else if state.code(&context.contract).is_some() {
+        Err(VmError::ContractExists)
+    } else if !state.pay(&context.caller, &context.contract, context.value) {
+        Err(VmError::InsufficientBalance)
+    } else {
+        state.set_code(&context.contract, program.clone());
+        Ok(None)
+    };
+
+    Receipt::new(
+        tx_id,
+        context.contract.clone(),
+        gas.min(gas_limit),
+        result,
+        vec![],
+    )
+}
+
+// run the contract of the context with its arguments, after paying it the value.
+// the caller has to undo the changes of a failed call.
+pub fn call(
+    tx_id: Hash,
+    context: &CallContext,
+    state: &mut dyn ContractState,
+    gas_limit: u64,
+) -> Receipt {
+    let mut machine = Machine {
+        stack: vec![],
+        gas_used: 0,
+        gas_limit,
+        logs: vec![],
+        context,
+    };
+
+    let result = machine.charge(BASE_GAS).and_then(|_| {
+        let program = state.code(&context.contract).ok_or(VmError::NoContract)?;
+
+        if !state.pay(&context.caller, &context.contract, context.value) {
+            return Err(VmError::InsufficientBalance);
+        }
+
+        machine.run(&program, state)
+    });
+
+    let logs = match result {
+        Ok(_) => machine.logs,
+        Err(_) => vec![],
+    };
+
+    Receipt::new(
+        tx_id,
+        context.contract.clone(),
+        machine.gas_used,
+        result,
+        logs,
+    )
+}
+
+struct Machine<'a> {
+    stack: Vec<u64>,
+    gas_used: u64,
+    gas_limit: u64,
+    logs: Vec<Log>,
+    context: &'a CallContext,
+}
+
+impl<'a> Machine<'a> {
+    // run until the end of the code or `Stop`. the word left on top is the output.
+    fn run(
+        &mut self,
+        program: &Program,
+        state: &mut dyn ContractState,
+    ) -> Result<Option<u64>, VmError> {
+        let context = self.context;
+        let mut pc = 0;
+
+        while let Some(instr) = program.code.get(pc) {
+            self.charge(instr.gas())?;
+            pc += 1;
+
+            match instr {
+                Instr::Push(word) => self.push(*word)?,
+                Instr::Pop => {
+                    self.pop()?;
+                }
+                Instr::Dup => {
+                    let word = self.peek(0)?;
+                    self.push(word)?;
+                }
+                Instr::Swap => {
+                    let top = self.pop()?;
+                    let second = self.pop()?;
+                    self.push(top)?;
+                    self.push(second)?;
+                }
+                Instr::Over => {
+                    let word = self.peek(1)?;
+                    self.push(word)?;
+                }
+                Instr::Add => self.binary(|a, b| a.checked_add(b).ok_or(VmError::Overflow))?,
+                Instr::Sub => self.binary(|a, b| a.checked_sub(b).ok_or(VmError::Overflow))?,
+                Instr::Mul => self.binary(|a, b| a.checked_mul(b).ok_or(VmError::Overflow))?,
+                Instr::Div => {
+                    self.binary(|a, b| a.checked_div(b).ok_or(VmError::DivisionByZero))?
+                }
+                Instr::Mod => {
+                    self.binary(|a, b| a.checked_rem(b).ok_or(VmError::DivisionByZero))?
+                }
+                Instr::Eq => self.binary(|a, b| Ok((a == b) as u64))?,
+                Instr::Lt => self.binary(|a, b| Ok((a < b) as u64))?,
+                Instr::Gt => self.binary(|a, b| Ok((a > b) as u64))?,
+                Instr::Not => {
+                    let word = self.pop()?;
+                    self.push((word == 0) as u64)?;
+                }
+                Instr::Jump(target) => pc = jump_target(program, *target)?,
+                Instr::JumpIf(target) => {
+                    if self.pop()? != 0 {
+                        pc = jump_target(program, *target)?;
+                    }
+                }
+                Instr::Arg => {
+                    let index = self.pop()?;
+                    let arg = usize::try_from(index)
+                        .ok()
+                        .and_then(|index| context.args.get(index))
+                        .ok_or(VmError::MissingArgument)?;
+                    self.push(*arg)?;
+                }
+                Instr::Caller => self.push(address_word(&context.caller))?,
+                Instr::Value => self.push(context.value)?,
+                Instr::Height => self.push(context.height)?,
+                Instr::Balance => self.push(state.balance_of(&context.contract))?,
+                Instr::Load => {
+                    let slot = self.pop()?;
+                    self.push(state.load(&context.contract, slot))?;
+                }
+                Instr::Store => {
+                    let slot = self.pop()?;
+                    let word = self.pop()?;
+                    state.store(&context.contract, slot, word);
+                }
+                Instr::Log(count) => {
+                    let count = *count as usize;
+                    if self.stack.len() < count {
+                        return Err(VmError::StackUnderflow);
+                    }
+
+                    let data = self.stack.split_off(self.stack.len() - count);
+                    self.logs.push(Log::new(context.contract.clone(), data));
+                }
+                Instr::Pay => {
+                    let amount = self.pop()?;
+                    if !state.pay(&context.contract, &context.caller, amount) {
+                        return Err(VmError::InsufficientBalance);
+                    }
+                }
+                Instr::Stop => break,
+                Instr::Revert => return Err(VmError::Reverted),
+            }
+        }
+
+        Ok(self.stack.last().copied())
+    }
+
+    fn charge(&mut self, gas: u64) -> Result<(), VmError> {
+        match self.gas_used.checked_add(gas) {
+            Some(used) if used <= self.gas_limit => {
+                self.gas_used = used;
+                Ok(())
+            }
+            _ => {
+                self.gas_used = self.gas_limit;
+                Err(VmError::OutOfGas)
+            }
+        }
+    }
+
+    fn push(&mut self, word: u64) -> Result<(), VmError> {
+        if self.stack.len() >= MAX_STACK_DEPTH {
+            return Err(VmError::StackOverflow);
+        }
+
+        self.stack.push(word);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<u64, VmError> {
+        self.stack.pop().ok_or(VmError::StackUnderflow)
+    }
+
+    // the word `depth` below the top.
+    fn peek(&self, depth: usize) -> Result<u64, VmError> {
+        self.stack
+            .len()
+            .checked_sub(depth + 1)
+            .map(|index| self.stack[index])
+            .ok_or(VmError::StackUnderflow)
+    }
+
+    fn binary(&mut self, op: impl Fn(u64, u64) -> Result<u64, VmError>) -> Result<(), VmError> {
+        let right = self.pop()?;
+        let left = self.pop()?;
+        let word = op(left, right)?;
+        self.push(word)
+    }
+}
+
+fn jump_target(program: &Program, target: u32) -> Result<usize, VmError> {
+    let target = target as usize;
+    if target > program.code.len() {
+        return Err(VmError::InvalidJump);
+    }
+
+    Ok(target)
+}
+
+impl Encodable for Program {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        bytes.extend((self.code.len() as u32).to_le_bytes());
+        for instr in self.code.iter() {
+            bytes.push(instr.code());
+            match instr {
+                Instr::Push(word) => bytes.extend(word.to_le_bytes()),
+                Instr::Jump(target) | Instr::JumpIf(target) => bytes.extend(target.to_le_bytes()),
+                Instr::Log(count) => bytes.push(*count),
+                _ => {}
+            }
+        }
+
+        bytes
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        let count = reader.u32()?;
+
+        let mut code = vec![];
+        for _ in 0..count {
+            code.push(match reader.u8()? {
+                0x00 => Instr::Push(reader.u64()?),
+                0x01 => Instr::Pop,
+                0x02 => Instr::Dup,
+                0x03 => Instr::Swap,
+                0x04 => Instr::Over,
+                0x10 => Instr::Add,
+                0x11 => Instr::Sub,
+                0x12 => Instr::Mul,
+                0x13 => Instr::Div,
+                0x14 => Instr::Mod,
+                0x20 => Instr::Eq,
+                0x21 => Instr::Lt,
+                0x22 => Instr::Gt,
+                0x23 => Instr::Not,
+                0x30 => Instr::Jump(reader.u32()?),
+                0x31 => Instr::JumpIf(reader.u32()?),
+                0x40 => Instr::Arg,
+                0x41 => Instr::Caller,
+                0x42 => Instr::Value,
+                0x43 => Instr::Height,
+                0x44 => Instr::Balance,
+                0x50 => Instr::Load,
+                0x51 => Instr::Store,
+                0x60 => Instr::Log(reader.u8()?),
+                0x61 => Instr::Pay,
+                0x70 => Instr::Stop,
+                0x71 => Instr::Revert,
+                _ => return Err(DecodeError::InvalidData),
+            });
+        }
+
+        Ok(Program { code })
+    }
+}
+
+#[test]
+fn test_contract_calls() {
+    use crate::backend::{
+        functions::address_of, store::file::funded_chain, Block, BlockValidationError, ChainParams,
+        Transaction, Transactions, ValueStore,
+    };
+    use crypto::ed25519;
+
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let alice_address = address_of(&alice_public);
+
+    let mut blk_chain = funded_chain(ChainParams::regtest(), &[(alice_address.clone(), 1000)]);
+
+    // adds its argument to a total kept at slot 0, and logs the new total.
+    let counter = Program::new(vec![
+        Instr::Push(0),
+        Instr::Load,
+        Instr::Push(0),
+        Instr::Arg,
+        Instr::Add,
+        Instr::Dup,
+        Instr::Push(0),
+        Instr::Store,
+        Instr::Dup,
+        Instr::Log(1),
+    ]);
+    let deploy = Transaction::deploy(alice_address.clone(), counter.clone(), 0)
+        .with_fee(100)
+        .sign(&alice);
+    let contract = contract_address(&deploy.id());
+
+    let call = |nonce, fee, args| {
+        Transaction::call(alice_address.clone(), contract.clone(), 0, args)
+            .with_nonce(nonce)
+            .with_fee(fee)
+            .sign(&alice)
+    };
+    let add = call(1, 100, vec![5]);
+    // enough to start, not to finish.
+    let starved = call(2, 20, vec![5]);
+    let no_args = call(3, 100, vec![]);
+    let nowhere = Transaction::call(alice_address.clone(), "nobody".to_string(), 0, vec![])
+        .with_nonce(4)
+        .with_fee(100)
+        .sign(&alice);
+
+    let mut transactions = Transactions::empty();
+    for tx in [&deploy, &add, &starved, &no_args, &nowhere] {
+        transactions.values_mut().push(tx.clone());
+    }
+    let last = blk_chain.last_header().unwrap();
+    let block = Block::mine_on(&last, transactions, "miner".to_string());
+    let block = blk_chain.seal_block(block).unwrap();
+    blk_chain.update_block(block).unwrap();
+
+    let deployed = blk_chain.receipt(&deploy.id()).unwrap();
+    assert_eq!(deployed.contract(), &contract);
+    assert_eq!(deployed.gas_used(), BASE_GAS + 10 * DEPLOY_GAS_PER_INSTR);
+
+    let added = blk_chain.receipt(&add.id()).unwrap();
+    assert_eq!(added.result(), &Ok(Some(5)));
+    assert_eq!(
+        added.gas_used(),
+        10 + 1 + 5 + 1 + 1 + 1 + 1 + 1 + 20 + 1 + 10
+    );
+    assert_eq!(added.logs(), &vec![Log::new(contract.clone(), vec![5])]);
+
+    // failed calls change nothing but still pay for their gas.
+    let starved = blk_chain.receipt(&starved.id()).unwrap();
+    assert_eq!(starved.result(), &Err(VmError::OutOfGas));
+    assert_eq!(starved.gas_used(), 20);
+    assert!(starved.logs().is_empty());
+    assert_eq!(
+        blk_chain.receipt(&no_args.id()).unwrap().result(),
+        &Err(VmError::MissingArgument)
+    );
+    assert_eq!(
+        blk_chain.receipt(&nowhere.id()).unwrap().result(),
+        &Err(VmError::NoContract)
+    );
+
+    // the contract sees the sender as its caller, so only the sender's key can call as them.
+    let (mallory, _) = ed25519::keypair(&[2; 32]);
+    let spoofed = Transaction::call(alice_address.clone(), contract.clone(), 0, vec![1])
+        .with_nonce(5)
+        .with_fee(100);
+    let last = blk_chain.last_header().unwrap();
+    let mut submit = |tx: &Transaction| {
+        let mut transactions = Transactions::empty();
+        transactions.values_mut().push(tx.clone());
+        blk_chain.update_block(Block::mine_on(&last, transactions, "miner".to_string()))
+    };
+    assert!(matches!(
+        submit(&spoofed),
+        Err(BlockValidationError::UnsignedTransaction)
+    ));
+    assert!(matches!(
+        submit(&spoofed.clone().sign(&mallory)),
+        Err(BlockValidationError::InvalidSignature)
+    ));
+    assert!(blk_chain.receipt(&spoofed.id()).is_none());
+
+    let state = blk_chain.value_store();
+    assert_eq!(state.storage(&contract, 0), 5);
+    assert_eq!(state.contract_code(&contract), Some(&counter));
+    assert_eq!(state.nonce(&alice_address), 5);
+    assert_eq!(
+        state.balance(&alice_address),
+        Some(1000 - 30 - 52 - 20 - 18 - 10)
+    );
+
+    // contracts are part of the state, so they survive a restore.
+    let restored = ValueStore::from_state(state.state_entries()).unwrap();
+    assert_eq!(restored.state_entries(), state.state_entries());
+    assert_eq!(restored.storage(&contract, 0), 5);
+}
@@ -8,4 +8,226 @@ pub enum BlockValidationError {
     InvalidInput,
     InsufficientInputVal,
     InvalidConinbaseTransaction,
+    MismatchedStateRoot,
+    MismatchedDifficulty,
+    // the block is already on the active chain or a side branch.
+    KnownBlock,
+    UnknownParent,
+    // no undo data is left for a block the reorg would have to disconnect. it is dropped once
+    // blocks are final or pruned, and isn't kept for blocks restored from a snapshot.
+    ReorgTooDeep,
+    // the block conflicts with a checkpoint of the chain params.
+    CheckpointMismatch,
+    // the block would replace a finalized one.
+    FinalityConflict,
+    InvalidVote,
+    // not later than the median time of the blocks before it.
+    TimestampBeforeMedianTimePast,
+    // further ahead of local time than the allowed drift.
+    TimestampTooFarInFuture,
+    BlockTooLarge {
+        size: usize,
+        max_block_size: usize,
+    },
+    TransactionTooLarge {
+        size: usize,
+        max_tx_size: usize,
+    },
+    TooManyTransactions {
+        count: usize,
+        max_block_transactions: usize,
+    },
+    // a signed transaction whose signature doesn't match its contents or sender.
+    InvalidSignature,
+    // fewer keys of a multisig policy signed than it requires.
+    InsufficientSignatures {
+        found: usize,
+        required: usize,
+    },
+    // only genesis allocations and miner rewards move value without the signature, multisig
+    // or script of the sender.
+    UnsignedTransaction,
+    MismatchedNonce {
+        expected: u64,
+        found: u64,
+    },
+    // a batch without payouts, or whose payouts don't add up to its value.
+    InvalidBatch,
+    // the block is before the start of a transaction's validity window.
+    TransactionNotYetValid,
+    // the block is past the end of a transaction's validity window.
+    TransactionExpired,
+    DataTooLarge {
+        size: usize,
+        max_tx_data: usize,
+    },
+    // the fee doesn't cover the data the transaction carries.
+    InsufficientDataFee {
+        fee: u64,
+        required: u64,
+    },
+    // a token with a bad symbol, too many decimals or no supply, or value in an asset
+    // nobody issued or the transaction can't move.
+    InvalidToken,
+    // no non-fungible token with the id exists, or it was burnt.
+    UnknownNft,
+    // only the owner of a non-fungible token can transfer or burn it.
+    NotNftOwner,
+    // the mint transaction was already applied.
+    NftExists,
+    // the script of a transaction refused the spend.
+    ScriptFailed(ScriptError),
+    Storage(StoreError),
+}
+
+// why a spending script refused a transaction.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScriptError {
+    // ran for more steps than the chain allows.
+    StepLimitExceeded,
+    StackUnderflow,
+    StackOverflow,
+    ItemTooLarge,
+    // wider than 8 bytes, or an overflowing sum.
+    InvalidNumber,
+    // an `Else` or `EndIf` without an `If`, or an `If` left open.
+    UnbalancedConditional,
+    // a verifying op whose condition didn't hold.
+    VerifyFailed,
+    // the block is below the height or time the script waits for.
+    Locked,
+    // the block is at or past the height or time the script had to be spent before.
+    Expired,
+    // the script finished without true on top of the stack.
+    Failed,
+}
+
+// why a contract deploy or call failed. the transaction is still mined and pays its gas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    OutOfGas,
+    StackUnderflow,
+    StackOverflow,
+    // arithmetic beyond the range of a word.
+    Overflow,
+    DivisionByZero,
+    // past the end of the code.
+    InvalidJump,
+    MissingArgument,
+    // the payer can't cover the amount.
+    InsufficientBalance,
+    // the contract ran `Revert`.
+    Reverted,
+    // nothing is deployed at the called address.
+    NoContract,
+    ContractExists,
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(std::io::Error),
+    Corrupted(DecodeError),
+    MissingBlock(u64),
+    NonContiguousHeight(u64),
+    InvalidStoredBlock(u64),
+    // the body of the block at this height was dropped by pruning.
+    Pruned(u64),
+}
+
+#[derive(Debug)]
+pub enum GenesisError {
+    Io(std::io::Error),
+    Parse(String),
+    InvalidDifficulty,
+    ExtraDataTooLong,
+    NoAllocations,
+    // empty address or zero value.
+    InvalidAllocation(String),
+    DuplicateAllocation(String),
+    SupplyOverflow,
+    SupplyMismatch { total_supply: u64, allocated: u64 },
+}
+
+// why a transaction was refused by the mempool.
+#[derive(Debug)]
+pub enum MempoolError {
+    // a transaction with the same hash is already pending.
+    Duplicate,
+    TooLarge {
+        size: usize,
+        max_tx_size: usize,
+    },
+    // only signed transactions are relayed.
+    Unsigned,
+    InvalidBatch,
+    InvalidSignature,
+    // an issue the chain would refuse, for its symbol, decimals, supply or value.
+    InvalidToken,
+    // mints, transfers or burns a non-fungible token along with some value.
+    InvalidNft,
+    // the script of the sender refuses the spend in the next block.
+    ScriptFailed(ScriptError),
+    // transfers or burns a non-fungible token the sender doesn't own in the current state.
+    NotNftOwner,
+    // the nonce doesn't directly follow the state and pending transactions of the sender.
+    MismatchedNonce {
+        expected: u64,
+        found: u64,
+    },
+    InsufficientBalance {
+        balance: u64,
+        required: u64,
+    },
+    DataTooLarge {
+        size: usize,
+        max_tx_data: usize,
+    },
+    InsufficientDataFee {
+        fee: u64,
+        required: u64,
+    },
+    // the pool is full of transactions paying at least as much per byte.
+    FeeTooLow,
+    // doesn't pay enough more than the pending transaction with the same nonce.
+    ReplacementUnderpriced {
+        fee: u64,
+        required: u64,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    InvalidData,
+    TrailingBytes,
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<DecodeError> for StoreError {
+    fn from(e: DecodeError) -> Self {
+        StoreError::Corrupted(e)
+    }
+}
+
+impl From<ScriptError> for BlockValidationError {
+    fn from(e: ScriptError) -> Self {
+        BlockValidationError::ScriptFailed(e)
+    }
+}
+
+impl From<StoreError> for BlockValidationError {
+    fn from(e: StoreError) -> Self {
+        BlockValidationError::Storage(e)
+    }
+}
+
+impl From<std::io::Error> for GenesisError {
+    fn from(e: std::io::Error) -> Self {
+        GenesisError::Io(e)
+    }
 }
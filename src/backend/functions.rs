@@ -17,7 +17,7 @@ pub const fn _i64_bytes(&item: &i64) -> [u8; 8] {
 }
 
 pub const fn hash_array() -> [u8; 32] {
-    [0 as u8; 32]
+    [0_u8; 32]
 }
 
 // little endian
@@ -33,22 +33,22 @@ pub const fn difficulty_bytes_as_u128(v: &[u8]) -> u128 {
     // println!("{}", v.into_boxed_slice().into());
 
     // move nums to the left side.
-    ((v[31] as u128) << 0xf * 8)
-        | ((v[30] as u128) << 0xe * 8)
-        | ((v[29] as u128) << 0xd * 8)
-        | ((v[28] as u128) << 0xc * 8)
-        | ((v[27] as u128) << 0xb * 8)
-        | ((v[26] as u128) << 0xa * 8)
-        | ((v[25] as u128) << 0x9 * 8)
-        | ((v[24] as u128) << 0x8 * 8)
-        | ((v[23] as u128) << 0x7 * 8)
-        | ((v[22] as u128) << 0x6 * 8)
-        | ((v[21] as u128) << 0x5 * 8)
-        | ((v[20] as u128) << 0x4 * 8)
-        | ((v[19] as u128) << 0x3 * 8)
-        | ((v[18] as u128) << 0x2 * 8)
-        | ((v[17] as u128) << 0x1 * 8)
-        | ((v[16] as u128) << 0x0 * 8)
+    ((v[31] as u128) << (0xf * 8))
+        | ((v[30] as u128) << (0xe * 8))
+        | ((v[29] as u128) << (0xd * 8))
+        | ((v[28] as u128) << (0xc * 8))
+        | ((v[27] as u128) << (0xb * 8))
+        | ((v[26] as u128) << (0xa * 8))
+        | ((v[25] as u128) << (0x9 * 8))
+        | ((v[24] as u128) << (0x8 * 8))
+        | ((v[23] as u128) << (0x7 * 8))
+        | ((v[22] as u128) << (0x6 * 8))
+        | ((v[21] as u128) << (0x5 * 8))
+        | ((v[20] as u128) << (0x4 * 8))
+        | ((v[19] as u128) << (0x3 * 8))
+        | ((v[18] as u128) << (0x2 * 8))
+        | ((v[17] as u128) << 8)
+        | (v[16] as u128)
 }
 
 pub const fn check_difficulty(hash_bytes: &[u8], difficulty: &u128) -> bool {
@@ -56,15 +56,14 @@ pub const fn check_difficulty(hash_bytes: &[u8], difficulty: &u128) -> bool {
         panic!();
     }
 
-    // println!("{}",difficulty_bytes_as_u128(&hash_bytes));
-    *difficulty > difficulty_bytes_as_u128(&hash_bytes)
+    // println!("{}",difficulty_bytes_as_u128(hash_bytes));
+    *difficulty > difficulty_bytes_as_u128(hash_bytes)
 }
 
 pub fn get_genesis_hasher() -> String {
     let mut init_wallet = Sha256::new();
     init_wallet.input_str("Genesis Block");
-    let result_hash = init_wallet.result_str();
-    result_hash
+    init_wallet.result_str()
 }
 
 pub fn get_hash(word: String) -> String{
@@ -73,3 +72,57 @@ pub fn get_hash(word: String) -> String{
 
     hasher.result_str()
 }
+
+// decode a hex hash string into its raw bytes.
+// malformed or missing digits are read as zero.
+pub fn hash_str_bytes(hash: &str) -> [u8; 32] {
+    let mut arr = hash_array();
+
+    for (idx, pair) in hash.as_bytes().chunks(2).take(32).enumerate() {
+        let text = std::str::from_utf8(pair).unwrap_or("00");
+        arr[idx] = u8::from_str_radix(text, 16).unwrap_or(0);
+    }
+
+    arr
+}
+
+pub fn hex_str(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// strict counterpart of `hex_str`. `None` on odd length or a non hex digit.
+pub fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+// address owned by an ed25519 public key.
+pub fn address_of(public_key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(public_key);
+
+    hasher.result_str()
+}
+
+// ed25519 signature check on hex encoded keys and signatures.
+pub fn verify_hex_signature(message: &[u8], public_key: &str, signature: &str) -> bool {
+    let (public_key, signature) = match (hex_bytes(public_key), hex_bytes(signature)) {
+        (Some(public_key), Some(signature)) => (public_key, signature),
+        _ => return false,
+    };
+
+    if public_key.len() != 32 || signature.len() != 64 {
+        return false;
+    }
+
+    crypto::ed25519::verify(message, &public_key, &signature)
+}
@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::backend::{
+    enums::GenesisError, params::ChainParams, types::Hash, Block, Transaction, Transactions,
+};
+
+// longest extra data message a genesis block may carry.
+pub const MAX_EXTRA_DATA_LEN: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Allocation {
+    address: Hash,
+    value: u64,
+}
+
+impl Allocation {
+    pub fn new(address: Hash, value: u64) -> Self {
+        Allocation { address, value }
+    }
+
+    pub fn address(&self) -> &Hash {
+        &self.address
+    }
+
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+}
+
+// description of a genesis block, as read from a json file.
+//
+// {
+//     "timestamp": 1700000000000000000,
+//     "difficulty": "0x000ffffffffffffffffffffffffffff",
+//     "extra_data": "first block",
+//     "total_supply": 5000,
+//     "allocations": [{ "address": "...", "value": 5000 }]
+// }
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GenesisSpec {
+    // nanoseconds, like block timestamps.
+    timestamp: u64,
+    // hex, with or without a leading `0x`.
+    difficulty: String,
+    #[serde(default)]
+    extra_data: String,
+    // has to match the sum of the allocations.
+    total_supply: u64,
+    allocations: Vec<Allocation>,
+}
+
+impl GenesisSpec {
+    pub fn from_json(text: &str) -> Result<Self, GenesisError> {
+        let spec: GenesisSpec =
+            serde_json::from_str(text).map_err(|e| GenesisError::Parse(e.to_string()))?;
+        spec.validate()?;
+
+        Ok(spec)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, GenesisError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn extra_data(&self) -> &str {
+        &self.extra_data
+    }
+
+    pub fn total_supply(&self) -> u64 {
+        self.total_supply
+    }
+
+    pub fn allocations(&self) -> &Vec<Allocation> {
+        &self.allocations
+    }
+
+    pub fn difficulty(&self) -> Result<u128, GenesisError> {
+        let digits = self
+            .difficulty
+            .strip_prefix("0x")
+            .unwrap_or(&self.difficulty);
+
+        match u128::from_str_radix(digits, 16) {
+            Ok(difficulty) if difficulty > 0 => Ok(difficulty),
+            _ => Err(GenesisError::InvalidDifficulty),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), GenesisError> {
+        self.difficulty()?;
+
+        if self.extra_data.len() > MAX_EXTRA_DATA_LEN {
+            return Err(GenesisError::ExtraDataTooLong);
+        }
+
+        if self.allocations.is_empty() {
+            return Err(GenesisError::NoAllocations);
+        }
+
+        let mut seen = HashSet::new();
+        let mut allocated: u64 = 0;
+
+        for allocation in self.allocations.iter() {
+            if allocation.address.is_empty() || allocation.value == 0 {
+                return Err(GenesisError::InvalidAllocation(allocation.address.clone()));
+            }
+
+            if !seen.insert(&allocation.address) {
+                return Err(GenesisError::DuplicateAllocation(
+                    allocation.address.clone(),
+                ));
+            }
+
+            allocated = allocated
+                .checked_add(allocation.value)
+                .ok_or(GenesisError::SupplyOverflow)?;
+        }
+
+        if allocated != self.total_supply {
+            return Err(GenesisError::SupplyMismatch {
+                total_supply: self.total_supply,
+                allocated,
+            });
+        }
+
+        Ok(())
+    }
+
+    // `base` with the difficulty of the spec. the chain checks blocks against it.
+    pub fn params(&self, base: ChainParams) -> Result<ChainParams, GenesisError> {
+        Ok(base.with_difficulty(self.difficulty()?))
+    }
+
+    // mine the genesis block for the network of `params`.
+    // the result only depends on the spec and the network.
+    pub fn build(&self, params: &ChainParams) -> Result<Block, GenesisError> {
+        self.validate()?;
+
+        let input = params.coinbase_input(&self.extra_data);
+        let mut transactions = Transactions::empty();
+        for allocation in self.allocations.iter() {
+            transactions.values_mut().push(Transaction::new(
+                input.clone(),
+                allocation.address.clone(),
+                allocation.value,
+            ));
+        }
+
+        Ok(Block::mine_genesis(
+            transactions,
+            self.allocations[0].address.clone(),
+            self.difficulty()?,
+            self.timestamp,
+        ))
+    }
+}
+
+#[test]
+fn test_genesis_spec() {
+    use crate::backend::{BlockChain, ChainParams};
+
+    let spec = r#"{
+        "timestamp": 1700000000000000000,
+        "difficulty": "0xffffffffffffffffffffffffffffffff",
+        "extra_data": "hello",
+        "total_supply": 300,
+        "allocations": [
+            { "address": "alice", "value": 100 },
+            { "address": "bob", "value": 200 }
+        ]
+    }"#;
+    let spec = GenesisSpec::from_json(spec).unwrap();
+    let params = spec.params(ChainParams::regtest()).unwrap();
+
+    let genesis = spec.build(&params).unwrap();
+    assert_eq!(
+        genesis.current_hash(),
+        spec.build(&params).unwrap().current_hash()
+    );
+    assert_ne!(
+        genesis.current_hash(),
+        spec.build(&ChainParams::testnet()).unwrap().current_hash()
+    );
+
+    let mut blk_chain = BlockChain::with_params(params);
+    blk_chain.update_block(genesis).unwrap();
+    assert_eq!(
+        blk_chain.value_store().balance(&"alice".to_string()),
+        Some(100)
+    );
+    assert_eq!(
+        blk_chain.value_store().balance(&"bob".to_string()),
+        Some(200)
+    );
+
+    let mut mismatched = spec.clone();
+    mismatched.total_supply = 301;
+    assert!(matches!(
+        mismatched.validate(),
+        Err(GenesisError::SupplyMismatch { allocated: 300, .. })
+    ));
+
+    let mut duplicated = spec;
+    duplicated
+        .allocations
+        .push(Allocation::new("bob".to_string(), 1));
+    duplicated.total_supply = 301;
+    assert!(matches!(
+        duplicated.validate(),
+        Err(GenesisError::DuplicateAllocation(_))
+    ));
+}
@@ -3,7 +3,11 @@ pub mod functions;
 mod traits;
 
 mod block;
+mod contract;
 mod enums;
+pub mod genesis;
+pub mod params;
+pub mod store;
 
 mod transaction;
 
@@ -11,9 +15,31 @@ mod types;
 
 // structs
 pub use block::block::Block;
-pub use block::blockchain::BlockChain;
+pub use block::blockchain::{BlockChain, ChainUpdate};
+pub use block::finality::Vote;
+pub use block::header::BlockHeader;
+pub use block::template::BlockTemplate;
+pub use contract::receipt::{Log, Receipt};
+pub use contract::vm::{address_word, contract_address, Instr, Program};
+pub use params::{ChainParams, Finality};
+pub use enums::{
+    BlockValidationError, DecodeError, GenesisError, MempoolError, ScriptError, StoreError,
+    VmError,
+};
+pub use genesis::{Allocation, GenesisSpec};
+pub use store::chainstore::{ChainStore, WriteBatch};
+pub use store::file::FileStore;
+pub use store::memory::MemoryStore;
+pub use traits::clock::{Clock, ManualClock, SystemClock};
+pub use traits::encodable::Encodable;
 pub use traits::hashable::Hashable;
 pub use transaction::transactions::Transactions;
-pub use transaction::transaction::Transaction;
-pub use transaction::valuestore::ValueStore;
+pub use transaction::asset::{asset_id, Token, NATIVE_ASSET};
+pub use transaction::htlc::{hash_lock, Htlc};
+pub use transaction::mempool::{Mempool, MempoolEvent, ReplacementRules};
+pub use transaction::multisig::MultisigPolicy;
+pub use transaction::nft::{nft_id, Nft};
+pub use transaction::script::{Op, Script, ScriptContext};
+pub use transaction::transaction::{TimeLock, Transaction, TransactionKind};
+pub use transaction::valuestore::{SpendContext, StateSnapshot, ValueStore};
 pub use functions::*;
@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+
+use crypto::{digest::Digest, sha2::Sha256};
+
+use crate::backend::types::Hash;
+
+pub const MAINNET_DIFFICULTY: u128 = 0x000ffffffffffffffffffffffffffff;
+pub const TESTNET_DIFFICULTY: u128 = 0x00ffffffffffffffffffffffffffffff;
+// every hash but the very largest passes, so blocks are found on the first try.
+pub const REGTEST_DIFFICULTY: u128 = u128::MAX;
+
+// consensus settings a chain is created with.
+#[derive(Debug, Clone)]
+pub struct ChainParams {
+    name: String,
+    // identifies the network. it is part of the genesis block, so networks never share one.
+    magic: u32,
+    difficulty: u128,
+    // hashed into the address the initial balance goes to.
+    genesis_message: String,
+    genesis_balance: u64,
+    block_reward: u64,
+    // a block has to be later than the median of this many blocks before it.
+    median_time_span: usize,
+    // how far ahead of local time a block timestamp may be, in nanoseconds.
+    max_future_drift: u64,
+    // serialized size limits, in bytes.
+    max_block_size: usize,
+    max_tx_size: usize,
+    max_block_transactions: usize,
+    // longest data payload a transaction may carry, and the fee every byte of it costs.
+    max_tx_data: usize,
+    data_fee_per_byte: u64,
+    // most ops a spending script may run.
+    max_script_steps: usize,
+    // what a unit of contract gas costs, and the most gas one transaction may buy.
+    gas_price: u64,
+    max_tx_gas: u64,
+    // blocks every valid chain has to contain, by height.
+    checkpoints: BTreeMap<u64, Hash>,
+    // block whose ancestors are trusted to carry valid scripts and signatures, so they aren't
+    // checked again. like a checkpoint, it has to be on every valid chain.
+    assumed_valid: Option<(u64, Hash)>,
+    finality: Finality,
+}
+
+// when a block can no longer be reverted by a reorg.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Finality {
+    // every block can be reorged away.
+    #[default]
+    None,
+    // blocks this many blocks below the tip are final.
+    Depth(u64),
+    // a block is final once `threshold` of the validators, given as hex ed25519 public keys,
+    // signed a vote for it.
+    Votes {
+        validators: Vec<Hash>,
+        threshold: usize,
+    },
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+impl ChainParams {
+    pub fn new() -> Self {
+        Self::mainnet()
+    }
+
+    pub fn mainnet() -> Self {
+        ChainParams {
+            name: "mainnet".to_string(),
+            magic: 0x5345_4d41,
+            difficulty: MAINNET_DIFFICULTY,
+            genesis_message: "Genesis Block".to_string(),
+            genesis_balance: 5000,
+            block_reward: 1,
+            median_time_span: 11,
+            max_future_drift: 2 * 60 * 60 * 1_000_000_000,
+            max_block_size: 1_000_000,
+            max_tx_size: 100_000,
+            max_block_transactions: 10_000,
+            max_tx_data: 256,
+            data_fee_per_byte: 1,
+            max_script_steps: 1_000,
+            gas_price: 1,
+            max_tx_gas: 1_000_000,
+            checkpoints: BTreeMap::new(),
+            assumed_valid: None,
+            finality: Finality::None,
+        }
+    }
+
+    pub fn testnet() -> Self {
+        ChainParams {
+            name: "testnet".to_string(),
+            magic: 0x5345_4d54,
+            difficulty: TESTNET_DIFFICULTY,
+            ..Self::mainnet()
+        }
+    }
+
+    // local chains for tests. mining is instant.
+    pub fn regtest() -> Self {
+        ChainParams {
+            name: "regtest".to_string(),
+            magic: 0x5345_4d52,
+            difficulty: REGTEST_DIFFICULTY,
+            ..Self::mainnet()
+        }
+    }
+
+    pub fn with_genesis(mut self, message: &str, balance: u64) -> Self {
+        self.genesis_message = message.to_string();
+        self.genesis_balance = balance;
+        self
+    }
+
+    pub fn with_difficulty(mut self, difficulty: u128) -> Self {
+        self.difficulty = difficulty;
+        self
+    }
+
+    pub fn with_timestamp_rules(mut self, median_time_span: usize, max_future_drift: u64) -> Self {
+        self.median_time_span = median_time_span.max(1);
+        self.max_future_drift = max_future_drift;
+        self
+    }
+
+    pub fn with_size_limits(
+        mut self,
+        max_block_size: usize,
+        max_tx_size: usize,
+        max_block_transactions: usize,
+    ) -> Self {
+        self.max_block_size = max_block_size;
+        self.max_tx_size = max_tx_size;
+        self.max_block_transactions = max_block_transactions;
+        self
+    }
+
+    pub fn with_data_limits(mut self, max_tx_data: usize, data_fee_per_byte: u64) -> Self {
+        self.max_tx_data = max_tx_data;
+        self.data_fee_per_byte = data_fee_per_byte;
+        self
+    }
+
+    pub fn with_max_script_steps(mut self, max_script_steps: usize) -> Self {
+        self.max_script_steps = max_script_steps;
+        self
+    }
+
+    pub fn with_gas(mut self, gas_price: u64, max_tx_gas: u64) -> Self {
+        self.gas_price = gas_price;
+        self.max_tx_gas = max_tx_gas;
+        self
+    }
+
+    pub fn with_block_reward(mut self, reward: u64) -> Self {
+        self.block_reward = reward;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn magic(&self) -> u32 {
+        self.magic
+    }
+
+    // the magic as written into the genesis coinbase transaction.
+    pub fn network_tag(&self) -> String {
+        format!("{:08x}", self.magic)
+    }
+
+    // input of a genesis coinbase transaction. extra data may follow the network tag.
+    pub fn coinbase_input(&self, extra_data: &str) -> String {
+        match extra_data.is_empty() {
+            true => self.network_tag(),
+            false => format!("{}:{}", self.network_tag(), extra_data),
+        }
+    }
+
+    pub fn is_own_coinbase_input(&self, input: &str) -> bool {
+        input.split(':').next() == Some(self.network_tag().as_str())
+    }
+
+    pub fn difficulty(&self) -> u128 {
+        self.difficulty
+    }
+
+    pub fn genesis_balance(&self) -> u64 {
+        self.genesis_balance
+    }
+
+    pub fn genesis_address(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.input_str(&self.genesis_message);
+        hasher.result_str()
+    }
+
+    pub fn block_reward(&self) -> u64 {
+        self.block_reward
+    }
+
+    pub fn median_time_span(&self) -> usize {
+        self.median_time_span
+    }
+
+    pub fn max_future_drift(&self) -> u64 {
+        self.max_future_drift
+    }
+
+    pub fn max_block_size(&self) -> usize {
+        self.max_block_size
+    }
+
+    pub fn max_tx_size(&self) -> usize {
+        self.max_tx_size
+    }
+
+    pub fn max_block_transactions(&self) -> usize {
+        self.max_block_transactions
+    }
+
+    pub fn max_tx_data(&self) -> usize {
+        self.max_tx_data
+    }
+
+    pub fn data_fee_per_byte(&self) -> u64 {
+        self.data_fee_per_byte
+    }
+
+    pub fn max_script_steps(&self) -> usize {
+        self.max_script_steps
+    }
+
+    pub fn gas_price(&self) -> u64 {
+        self.gas_price
+    }
+
+    pub fn max_tx_gas(&self) -> u64 {
+        self.max_tx_gas
+    }
+
+    // least fee a transaction carrying `len` bytes of data has to pay.
+    pub fn data_fee(&self, len: usize) -> u64 {
+        (len as u64).saturating_mul(self.data_fee_per_byte)
+    }
+
+    pub fn with_checkpoint(mut self, height: u64, hash: Hash) -> Self {
+        self.checkpoints.insert(height, hash);
+        self
+    }
+
+    pub fn with_assumed_valid(mut self, height: u64, hash: Hash) -> Self {
+        self.assumed_valid = Some((height, hash));
+        self
+    }
+
+    pub fn with_finality(mut self, finality: Finality) -> Self {
+        self.finality = finality;
+        self
+    }
+
+    pub fn finality(&self) -> &Finality {
+        &self.finality
+    }
+
+    pub fn checkpoints(&self) -> &BTreeMap<u64, Hash> {
+        &self.checkpoints
+    }
+
+    pub fn checkpoint(&self, height: u64) -> Option<&Hash> {
+        self.checkpoints.get(&height)
+    }
+
+    // highest checkpoint at or below `height`.
+    pub fn last_checkpoint_below(&self, height: u64) -> Option<(u64, &Hash)> {
+        self.checkpoints
+            .range(..=height)
+            .next_back()
+            .map(|(height, hash)| (*height, hash))
+    }
+
+    pub fn assumed_valid(&self) -> Option<(u64, &Hash)> {
+        self.assumed_valid
+            .as_ref()
+            .map(|(height, hash)| (*height, hash))
+    }
+
+    // whether a block with this hash may sit at this height.
+    pub fn matches_checkpoint(&self, height: u64, hash: &Hash) -> bool {
+        let matches_assumed = match self.assumed_valid() {
+            Some((assumed, assumed_hash)) if assumed == height => assumed_hash == hash,
+            _ => true,
+        };
+
+        match self.checkpoints.get(&height) {
+            Some(checkpoint) => checkpoint == hash && matches_assumed,
+            None => matches_assumed,
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::backend::{
+    enums::StoreError,
+    transaction::valuestore::StateSnapshot,
+    types::{Hash, StateEntry, StateWrite},
+    Block, BlockHeader,
+};
+
+// storage backend for the blocks of the active chain and the account state.
+pub trait ChainStore {
+    fn height_of(&self, hash: &Hash) -> Option<u64>;
+
+    // fails with `StoreError::Pruned` once the body has been dropped.
+    fn block_by_height(&self, height: u64) -> Result<Option<Block>, StoreError>;
+
+    // headers are kept for every block, pruned or not.
+    fn header_by_height(&self, height: u64) -> Result<Option<BlockHeader>, StoreError>;
+
+    // number of stored blocks. heights run from 0 to `block_count() - 1`.
+    fn block_count(&self) -> u64;
+
+    fn state(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError>;
+
+    fn state_entries(&self) -> Result<Vec<StateEntry>, StoreError>;
+
+    // apply every operation of the batch, or none of them.
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError>;
+
+    fn put_snapshot(&mut self, snapshot: StateSnapshot) -> Result<(), StoreError>;
+
+    // newest snapshot which is intact and matches a stored block.
+    // blocks above its height still have to be applied to it.
+    fn latest_snapshot(&self) -> Result<Option<StateSnapshot>, StoreError>;
+
+    fn latest_snapshot_height(&self) -> Option<u64>;
+
+    // drop the bodies of every block below `height`, keeping their headers.
+    fn prune_bodies(&mut self, height: u64) -> Result<(), StoreError>;
+
+    // bodies below this height are gone.
+    fn pruned_below(&self) -> u64;
+
+    fn block_by_hash(&self, hash: &Hash) -> Result<Option<Block>, StoreError> {
+        match self.height_of(hash) {
+            Some(height) => self.block_by_height(height),
+            None => Ok(None),
+        }
+    }
+
+    fn header_by_hash(&self, hash: &Hash) -> Result<Option<BlockHeader>, StoreError> {
+        match self.height_of(hash) {
+            Some(height) => self.header_by_height(height),
+            None => Ok(None),
+        }
+    }
+
+    fn last(&self) -> Result<Option<Block>, StoreError> {
+        match self.block_count() {
+            0 => Ok(None),
+            count => self.block_by_height(count - 1),
+        }
+    }
+
+    fn last_header(&self) -> Result<Option<BlockHeader>, StoreError> {
+        match self.block_count() {
+            0 => Ok(None),
+            count => self.header_by_height(count - 1),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BatchOp {
+    PutBlock { height: u64, block: Block },
+    PutState { key: Vec<u8>, value: Vec<u8> },
+    DeleteState { key: Vec<u8> },
+}
+
+#[derive(Debug, Default)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        WriteBatch { ops: vec![] }
+    }
+
+    pub fn put_block(&mut self, height: u64, block: Block) {
+        self.ops.push(BatchOp::PutBlock { height, block });
+    }
+
+    pub fn put_state(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.ops.push(BatchOp::PutState { key, value });
+    }
+
+    pub fn delete_state(&mut self, key: Vec<u8>) {
+        self.ops.push(BatchOp::DeleteState { key });
+    }
+
+    // add state writes as produced by `ValueStore::commit`.
+    pub fn extend_state(&mut self, writes: Vec<StateWrite>) {
+        for (key, value) in writes {
+            match value {
+                Some(value) => self.put_state(key, value),
+                None => self.delete_state(key),
+            }
+        }
+    }
+
+    pub fn ops(&self) -> &Vec<BatchOp> {
+        &self.ops
+    }
+
+    pub fn into_ops(self) -> Vec<BatchOp> {
+        self.ops
+    }
+}
+
+// block heights of a batch must continue the stored chain without gaps.
+pub fn check_heights(batch: &WriteBatch, block_count: u64) -> Result<(), StoreError> {
+    let mut next = block_count;
+
+    for op in batch.ops() {
+        if let BatchOp::PutBlock { height, .. } = op {
+            if *height > next {
+                return Err(StoreError::NonContiguousHeight(*height));
+            }
+            next = next.max(height + 1);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,872 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crypto::{digest::Digest, sha2::Sha256};
+
+use crate::backend::{
+    enums::{DecodeError, StoreError},
+    functions::hash_array,
+    store::chainstore::{check_heights, BatchOp, ChainStore, WriteBatch},
+    traits::encodable::{put_bytes, ByteReader},
+    transaction::valuestore::StateSnapshot,
+    types::{Hash, StateEntry},
+    Block, BlockHeader, Encodable,
+};
+
+const INDEX_FILE: &str = "index.dat";
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+// older snapshots are kept in case the newest one turns out to be damaged.
+const KEPT_SNAPSHOTS: usize = 2;
+const DEFAULT_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+// payload length, height, flags, checksum.
+const RECORD_HEADER_LEN: u64 = 4 + 8 + 1 + 4;
+// set on the last record of every batch.
+const COMMIT_FLAG: u8 = 1;
+const PRUNED_FILE: &str = "pruned.dat";
+
+// where a block record lives inside the segment files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Location {
+    segment: u32,
+    offset: u64,
+    len: u32,
+}
+
+impl Location {
+    fn end(&self) -> u64 {
+        self.offset + RECORD_HEADER_LEN + self.len as u64
+    }
+}
+
+struct Record {
+    height: u64,
+    flags: u8,
+    location: Location,
+    block: Block,
+}
+
+// append-only block log.
+// blocks are appended to numbered segment files, and an index file maps heights
+// to their records and keeps every header, so whole segments can be deleted when
+// pruning. the state is only written out as snapshots tagged with the block
+// they were taken at. on open, torn records at the end of the log or the index are
+// cut off, the newest usable snapshot is loaded, and blocks past it are left for the
+// chain to replay.
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+    segment_size: u64,
+    locations: Vec<Location>,
+    headers: Vec<BlockHeader>,
+    heights: HashMap<Hash, u64>,
+    // end of the last committed record.
+    tail: (u32, u64),
+    pruned_below: u64,
+    snapshot_height: Option<u64>,
+    state: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl FileStore {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<FileStore, StoreError> {
+        Self::open_with_segment_size(dir, DEFAULT_SEGMENT_SIZE)
+    }
+
+    pub fn open_with_segment_size<P: AsRef<Path>>(
+        dir: P,
+        segment_size: u64,
+    ) -> Result<FileStore, StoreError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut store = FileStore {
+            dir,
+            segment_size,
+            locations: vec![],
+            headers: vec![],
+            heights: HashMap::new(),
+            tail: (0, 0),
+            pruned_below: 0,
+            snapshot_height: None,
+            state: BTreeMap::new(),
+        };
+
+        store.recover()?;
+
+        let pruned_path = store.dir.join(PRUNED_FILE);
+        if pruned_path.exists() {
+            store.pruned_below = ByteReader::new(&fs::read(pruned_path)?).u64()?;
+        }
+
+        if let Some(snapshot) = store.latest_snapshot()? {
+            store.snapshot_height = Some(snapshot.height());
+            store.state = snapshot.entries().iter().cloned().collect();
+        }
+
+        Ok(store)
+    }
+
+    fn segment_path(&self, segment: u32) -> PathBuf {
+        self.dir.join(format!("segment-{:05}.log", segment))
+    }
+
+    fn recover(&mut self) -> Result<(), StoreError> {
+        let mut entries = self.read_index()?;
+
+        // the index may point past what actually reached the log.
+        while let Some((_, _, location, _)) = entries.last() {
+            if self.read_record(location).is_ok() {
+                break;
+            }
+            entries.pop();
+        }
+        self.set_index_len(entries.last().map_or(0, |(_, _, _, end)| *end))?;
+
+        for (height, header, location, _) in entries.into_iter() {
+            self.tail = (location.segment, location.end());
+            self.index_location(height, header, location);
+        }
+
+        // committed records written after the last index entry are indexed again.
+        let (mut segment, mut offset) = self.tail;
+        let mut pending = vec![];
+
+        loop {
+            let path = self.segment_path(segment);
+            if !path.exists() || offset >= fs::metadata(&path)?.len() {
+                if self.segment_path(segment + 1).exists() {
+                    segment += 1;
+                    offset = 0;
+                    continue;
+                }
+                break;
+            }
+
+            let record = match self.scan_record(segment, offset) {
+                Ok(record) => record,
+                // torn write.
+                Err(_) => break,
+            };
+
+            offset = record.location.end();
+            let is_commit = record.flags & COMMIT_FLAG != 0;
+            pending.push(record);
+
+            if is_commit {
+                for record in pending.drain(..) {
+                    let header = record.block.header();
+                    self.append_index(record.height, &header, &record.location)?;
+                    self.index_location(record.height, header, record.location);
+                    self.tail = (record.location.segment, record.location.end());
+                }
+            }
+        }
+
+        self.truncate_log()
+    }
+
+    // drop everything past the tail. it belongs to batches which never completed.
+    fn truncate_log(&self) -> Result<(), StoreError> {
+        let (segment, offset) = self.tail;
+        let path = self.segment_path(segment);
+
+        if path.exists() {
+            OpenOptions::new()
+                .write(true)
+                .open(&path)?
+                .set_len(offset)?;
+        }
+
+        let mut later = segment + 1;
+        while self.segment_path(later).exists() {
+            fs::remove_file(self.segment_path(later))?;
+            later += 1;
+        }
+
+        Ok(())
+    }
+
+    // entries up to the first damaged one, each with the index length up to its end.
+    fn read_index(&self) -> Result<Vec<(u64, BlockHeader, Location, u64)>, StoreError> {
+        let path = self.dir.join(INDEX_FILE);
+
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+
+        let bytes = fs::read(path)?;
+        let mut reader = ByteReader::new(&bytes);
+        let mut entries = vec![];
+
+        while let Ok(body) = reader.bytes() {
+            let sum = match reader.take(4) {
+                Ok(sum) => sum,
+                Err(_) => break,
+            };
+
+            if checksum(&body) != sum {
+                break;
+            }
+
+            let mut body_reader = ByteReader::new(&body);
+            let height = body_reader.u64()?;
+            let location = Location {
+                segment: body_reader.u32()?,
+                offset: body_reader.u64()?,
+                len: body_reader.u32()?,
+            };
+            let header = BlockHeader::decode(&mut body_reader)?;
+
+            entries.push((height, header, location, reader.position() as u64));
+        }
+
+        Ok(entries)
+    }
+
+    fn set_index_len(&self, len: u64) -> Result<(), StoreError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(INDEX_FILE))?;
+        file.set_len(len)?;
+
+        Ok(())
+    }
+
+    fn append_index(
+        &self,
+        height: u64,
+        header: &BlockHeader,
+        location: &Location,
+    ) -> Result<(), StoreError> {
+        let mut body = vec![];
+        body.extend(height.to_le_bytes());
+        body.extend(location.segment.to_le_bytes());
+        body.extend(location.offset.to_le_bytes());
+        body.extend(location.len.to_le_bytes());
+        body.extend(header.encode());
+
+        let mut entry = vec![];
+        put_bytes(&mut entry, &body);
+        entry.extend(checksum(&body));
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(INDEX_FILE))?;
+        file.write_all(&entry)?;
+        file.sync_data()?;
+
+        Ok(())
+    }
+
+    fn index_location(&mut self, height: u64, header: BlockHeader, location: Location) {
+        let height_idx = height as usize;
+        let hash = header.current_hash_str();
+
+        if height_idx < self.locations.len() {
+            self.locations[height_idx] = location;
+            let replaced = std::mem::replace(&mut self.headers[height_idx], header);
+            self.heights.remove(replaced.current_hash());
+        } else {
+            self.locations.push(location);
+            self.headers.push(header);
+        }
+
+        self.heights.insert(hash, height);
+    }
+
+    // read and check the record starting at `offset`.
+    fn scan_record(&self, segment: u32, offset: u64) -> Result<Record, StoreError> {
+        let mut file = File::open(self.segment_path(segment))?;
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut header = [0_u8; RECORD_HEADER_LEN as usize];
+        file.read_exact(&mut header)?;
+
+        let mut reader = ByteReader::new(&header);
+        let len = reader.u32()?;
+        let height = reader.u64()?;
+        let flags = reader.u8()?;
+
+        let location = Location {
+            segment,
+            offset,
+            len,
+        };
+
+        Ok(Record {
+            height,
+            flags,
+            location,
+            block: self.read_record(&location)?,
+        })
+    }
+
+    fn read_record(&self, location: &Location) -> Result<Block, StoreError> {
+        let mut file = File::open(self.segment_path(location.segment))?;
+        file.seek(SeekFrom::Start(location.offset))?;
+
+        let mut record = vec![0_u8; (RECORD_HEADER_LEN + location.len as u64) as usize];
+        file.read_exact(&mut record)?;
+
+        let (header, payload) = record.split_at(RECORD_HEADER_LEN as usize);
+        let (summed_header, sum) = header.split_at(header.len() - 4);
+
+        let mut summed = summed_header.to_vec();
+        summed.extend(payload);
+
+        if summed_header[0..4] != location.len.to_le_bytes() || checksum(&summed) != sum {
+            return Err(StoreError::Corrupted(DecodeError::InvalidData));
+        }
+
+        Ok(Block::from_bytes(payload)?)
+    }
+
+    fn encode_record(height: u64, flags: u8, block: &Block) -> Vec<u8> {
+        let payload = block.encode();
+
+        let mut record = vec![];
+        record.extend((payload.len() as u32).to_le_bytes());
+        record.extend(height.to_le_bytes());
+        record.push(flags);
+
+        let mut summed = record.clone();
+        summed.extend(&payload);
+
+        record.extend(checksum(&summed));
+        record.extend(&payload);
+
+        record
+    }
+
+    fn snapshot_path(&self, height: u64) -> PathBuf {
+        self.dir
+            .join(format!("{}{:020}.dat", SNAPSHOT_PREFIX, height))
+    }
+
+    // heights of the snapshot files on disk, newest first.
+    fn snapshot_heights(&self) -> Result<Vec<u64>, StoreError> {
+        let mut heights = vec![];
+
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+
+            let height = name
+                .strip_prefix(SNAPSHOT_PREFIX)
+                .and_then(|rest| rest.strip_suffix(".dat"))
+                .and_then(|height| height.parse::<u64>().ok());
+
+            if let Some(height) = height {
+                heights.push(height);
+            }
+        }
+
+        heights.sort_unstable_by(|a, b| b.cmp(a));
+
+        Ok(heights)
+    }
+
+    fn read_snapshot(&self, height: u64) -> Result<StateSnapshot, StoreError> {
+        let bytes = fs::read(self.snapshot_path(height))?;
+
+        if bytes.len() < 4 || checksum(&bytes[4..]) != bytes[0..4] {
+            return Err(StoreError::Corrupted(DecodeError::InvalidData));
+        }
+
+        let snapshot = StateSnapshot::from_bytes(&bytes[4..])?;
+
+        // it has to belong to the block stored at its height.
+        match self.header_by_height(snapshot.height())? {
+            Some(header) if header.current_hash() == snapshot.block_hash() => Ok(snapshot),
+            _ => Err(StoreError::MissingBlock(snapshot.height())),
+        }
+    }
+
+    // append the blocks of one batch and flush them. returns where each one landed.
+    fn append_blocks(&self, blocks: &[(u64, Block)]) -> Result<Vec<Location>, StoreError> {
+        let (mut segment, mut offset) = self.tail;
+        let mut file: Option<File> = None;
+        let mut locations = vec![];
+
+        for (idx, (height, block)) in blocks.iter().enumerate() {
+            if offset >= self.segment_size {
+                if let Some(full) = file.take() {
+                    full.sync_data()?;
+                }
+                segment += 1;
+                offset = 0;
+            }
+
+            if file.is_none() {
+                let mut opened = OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .write(true)
+                    .open(self.segment_path(segment))?;
+                // leftovers of a failed batch are overwritten.
+                opened.set_len(offset)?;
+                opened.seek(SeekFrom::Start(offset))?;
+                file = Some(opened);
+            }
+
+            let flags = if idx == blocks.len() - 1 {
+                COMMIT_FLAG
+            } else {
+                0
+            };
+            let record = Self::encode_record(*height, flags, block);
+            file.as_mut().unwrap().write_all(&record)?;
+
+            let location = Location {
+                segment,
+                offset,
+                len: (record.len() as u64 - RECORD_HEADER_LEN) as u32,
+            };
+            offset = location.end();
+            locations.push(location);
+        }
+
+        if let Some(file) = file {
+            file.sync_data()?;
+        }
+
+        Ok(locations)
+    }
+}
+
+fn checksum(bytes: &[u8]) -> [u8; 4] {
+    let mut hasher = Sha256::new();
+    hasher.input(bytes);
+
+    let mut arr = hash_array();
+    hasher.result(&mut arr);
+
+    [arr[0], arr[1], arr[2], arr[3]]
+}
+
+// write to a temporary file first, so readers never see half of it.
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), StoreError> {
+    let tmp_path = path.with_extension("tmp");
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+
+    fs::rename(tmp_path, path)?;
+
+    Ok(())
+}
+
+impl ChainStore for FileStore {
+    fn height_of(&self, hash: &Hash) -> Option<u64> {
+        self.heights.get(hash).copied()
+    }
+
+    fn block_by_height(&self, height: u64) -> Result<Option<Block>, StoreError> {
+        if height < self.pruned_below && height < self.block_count() {
+            return Err(StoreError::Pruned(height));
+        }
+
+        match self.locations.get(height as usize) {
+            Some(location) => Ok(Some(self.read_record(location)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn header_by_height(&self, height: u64) -> Result<Option<BlockHeader>, StoreError> {
+        Ok(self.headers.get(height as usize).cloned())
+    }
+
+    fn block_count(&self) -> u64 {
+        self.locations.len() as u64
+    }
+
+    fn state(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(self.state.get(key).cloned())
+    }
+
+    fn state_entries(&self) -> Result<Vec<StateEntry>, StoreError> {
+        Ok(self
+            .state
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError> {
+        check_heights(&batch, self.block_count())?;
+
+        let mut state = self.state.clone();
+        let mut blocks = vec![];
+
+        for op in batch.into_ops() {
+            match op {
+                BatchOp::PutBlock { height, block } => blocks.push((height, block)),
+                BatchOp::PutState { key, value } => {
+                    state.insert(key, value);
+                }
+                BatchOp::DeleteState { key } => {
+                    state.remove(&key);
+                }
+            }
+        }
+
+        // blocks must be durable before the index refers to them.
+        let locations = self.append_blocks(&blocks)?;
+
+        for ((height, block), location) in blocks.iter().zip(locations) {
+            let header = block.header();
+            self.append_index(*height, &header, &location)?;
+            self.index_location(*height, header, location);
+            self.tail = (location.segment, location.end());
+        }
+
+        self.state = state;
+
+        // a snapshot of a replaced block no longer describes the chain.
+        let replaced = blocks.iter().map(|(height, _)| *height).min();
+        if let (Some(replaced), Some(snapshot_height)) = (replaced, self.snapshot_height) {
+            if replaced <= snapshot_height {
+                self.snapshot_height = self.latest_snapshot()?.map(|snapshot| snapshot.height());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn put_snapshot(&mut self, snapshot: StateSnapshot) -> Result<(), StoreError> {
+        let encoded = snapshot.encode();
+        let mut bytes = checksum(&encoded).to_vec();
+        bytes.extend(encoded);
+
+        write_atomic(&self.snapshot_path(snapshot.height()), &bytes)?;
+        self.snapshot_height = Some(snapshot.height());
+
+        for height in self.snapshot_heights()?.into_iter().skip(KEPT_SNAPSHOTS) {
+            fs::remove_file(self.snapshot_path(height))?;
+        }
+
+        Ok(())
+    }
+
+    fn latest_snapshot(&self) -> Result<Option<StateSnapshot>, StoreError> {
+        for height in self.snapshot_heights()? {
+            if let Ok(snapshot) = self.read_snapshot(height) {
+                return Ok(Some(snapshot));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn latest_snapshot_height(&self) -> Option<u64> {
+        self.snapshot_height
+    }
+
+    fn prune_bodies(&mut self, height: u64) -> Result<(), StoreError> {
+        let below = height.min(self.block_count());
+
+        if below <= self.pruned_below {
+            return Ok(());
+        }
+
+        write_atomic(&self.dir.join(PRUNED_FILE), &below.to_le_bytes())?;
+        self.pruned_below = below;
+
+        // segments holding no kept body can go. the active one always stays.
+        let kept = self
+            .locations
+            .iter()
+            .skip(below as usize)
+            .map(|location| location.segment)
+            .collect::<HashSet<u32>>();
+
+        for segment in 0..self.tail.0 {
+            let path = self.segment_path(segment);
+            if !kept.contains(&segment) && path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pruned_below(&self) -> u64 {
+        self.pruned_below
+    }
+}
+
+// unique scratch directory for tests.
+#[cfg(test)]
+pub(crate) fn temp_store_dir(name: &str) -> PathBuf {
+    let nanos = chrono::Utc::now().timestamp_nanos();
+    let dir = std::env::temp_dir().join(format!("seman-{}-{}-{}", name, std::process::id(), nanos));
+    _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+// regtest genesis block giving each address its value.
+#[cfg(test)]
+pub(crate) fn funded_genesis(
+    params: &crate::backend::ChainParams,
+    allocations: &[(Hash, u64)],
+) -> Block {
+    use crate::backend::{Transaction, Transactions};
+
+    let mut transactions = Transactions::empty();
+    for (address, value) in allocations {
+        transactions.values_mut().push(Transaction::new(
+            params.coinbase_input(""),
+            address.clone(),
+            *value,
+        ));
+    }
+
+    Block::mine_genesis(transactions, "a".to_string(), params.difficulty(), 1)
+}
+
+// chain on `params` which starts from `funded_genesis`.
+#[cfg(test)]
+pub(crate) fn funded_chain(
+    params: crate::backend::ChainParams,
+    allocations: &[(Hash, u64)],
+) -> crate::backend::BlockChain {
+    let genesis = funded_genesis(&params, allocations);
+    let mut blk_chain = crate::backend::BlockChain::with_params(params);
+    blk_chain.update_block(genesis).unwrap();
+
+    blk_chain
+}
+
+#[test]
+fn test_file_store_reopen() {
+    use crate::backend::params::REGTEST_DIFFICULTY;
+    use crate::backend::Transactions;
+
+    let dir = temp_store_dir("file-store");
+    let blocks = (0..3)
+        .map(|idx| {
+            Block::mine_with(
+                idx,
+                Transactions::empty(),
+                "miner".to_string(),
+                REGTEST_DIFFICULTY,
+            )
+        })
+        .collect::<Vec<Block>>();
+
+    {
+        let mut store = FileStore::open(&dir).unwrap();
+        let mut batch = WriteBatch::new();
+        for (height, block) in blocks.iter().enumerate() {
+            batch.put_block(height as u64, block.clone());
+        }
+        batch.put_state(b"key".to_vec(), vec![1, 2]);
+        store.write(batch).unwrap();
+
+        for height in 0..3 {
+            let entries = vec![(b"key".to_vec(), vec![height as u8])];
+            let hash = blocks[height as usize].current_hash_str();
+            store
+                .put_snapshot(StateSnapshot::new(height, hash, entries))
+                .unwrap();
+        }
+    }
+
+    // only the newest snapshots are kept.
+    assert!(!dir
+        .join(format!("{}{:020}.dat", SNAPSHOT_PREFIX, 0))
+        .exists());
+
+    let store = FileStore::open(&dir).unwrap();
+    let hash = blocks[1].current_hash_str();
+    assert_eq!(store.block_count(), 3);
+    assert_eq!(
+        store
+            .block_by_hash(&hash)
+            .unwrap()
+            .unwrap()
+            .current_hash_str(),
+        hash
+    );
+    assert_eq!(store.latest_snapshot().unwrap().unwrap().height(), 2);
+    assert_eq!(store.state(b"key").unwrap(), Some(vec![2]));
+    drop(store);
+
+    // a damaged snapshot is passed over for the one before it.
+    let newest = dir.join(format!("{}{:020}.dat", SNAPSHOT_PREFIX, 2));
+    let mut bytes = fs::read(&newest).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(&newest, bytes).unwrap();
+
+    let store = FileStore::open(&dir).unwrap();
+    assert_eq!(store.latest_snapshot().unwrap().unwrap().height(), 1);
+    assert_eq!(store.state(b"key").unwrap(), Some(vec![1]));
+
+    _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_file_store_torn_writes() {
+    use crate::backend::params::REGTEST_DIFFICULTY;
+    use crate::backend::Transactions;
+
+    let dir = temp_store_dir("torn-writes");
+    let blocks = (0..3)
+        .map(|idx| {
+            Block::mine_with(
+                idx,
+                Transactions::empty(),
+                "miner".to_string(),
+                REGTEST_DIFFICULTY,
+            )
+        })
+        .collect::<Vec<Block>>();
+    let segment_len = |segment: u32| {
+        fs::metadata(dir.join(format!("segment-{:05}.log", segment)))
+            .unwrap()
+            .len()
+    };
+    let set_len = |path: PathBuf, len: u64| {
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_len(len)
+            .unwrap()
+    };
+
+    {
+        // tiny segments so every batch starts a segment of its own.
+        let mut store = FileStore::open_with_segment_size(&dir, 1).unwrap();
+        for (height, block) in blocks.iter().enumerate() {
+            let mut batch = WriteBatch::new();
+            batch.put_block(height as u64, block.clone());
+            store.write(batch).unwrap();
+        }
+    }
+
+    // the index entry of the last block is half written,
+    // and so is the record itself.
+    let index_len = fs::metadata(dir.join(INDEX_FILE)).unwrap().len();
+    set_len(dir.join(INDEX_FILE), index_len - 20);
+    set_len(dir.join("segment-00002.log"), segment_len(2) - 3);
+
+    let store = FileStore::open_with_segment_size(&dir, 1).unwrap();
+    assert_eq!(store.block_count(), 2);
+    assert_eq!(
+        store.last().unwrap().unwrap().current_hash_str(),
+        blocks[1].current_hash_str()
+    );
+    assert!(!dir.join("segment-00002.log").exists() || segment_len(2) == 0);
+    drop(store);
+
+    // a committed record whose index entry was lost is indexed again.
+    let mut store = FileStore::open_with_segment_size(&dir, 1).unwrap();
+    let mut batch = WriteBatch::new();
+    batch.put_block(2, blocks[2].clone());
+    store.write(batch).unwrap();
+    drop(store);
+
+    let index_len = fs::metadata(dir.join(INDEX_FILE)).unwrap().len();
+    set_len(dir.join(INDEX_FILE), index_len - 1);
+
+    let store = FileStore::open_with_segment_size(&dir, 1).unwrap();
+    assert_eq!(store.block_count(), 3);
+    assert_eq!(
+        store
+            .block_by_height(2)
+            .unwrap()
+            .unwrap()
+            .current_hash_str(),
+        blocks[2].current_hash_str()
+    );
+    drop(store);
+
+    // a batch cut short before its commit record is dropped as a whole.
+    let committed_len = segment_len(2);
+    let index_len = fs::metadata(dir.join(INDEX_FILE)).unwrap().len();
+    let mut store = FileStore::open_with_segment_size(&dir, 1024 * 1024).unwrap();
+    let mut batch = WriteBatch::new();
+    batch.put_block(
+        3,
+        Block::mine_with(
+            3,
+            Transactions::empty(),
+            "miner".to_string(),
+            REGTEST_DIFFICULTY,
+        ),
+    );
+    batch.put_block(
+        4,
+        Block::mine_with(
+            4,
+            Transactions::empty(),
+            "miner".to_string(),
+            REGTEST_DIFFICULTY,
+        ),
+    );
+    store.write(batch).unwrap();
+    drop(store);
+
+    set_len(dir.join(INDEX_FILE), index_len);
+    set_len(dir.join("segment-00002.log"), segment_len(2) - 1);
+
+    let store = FileStore::open_with_segment_size(&dir, 1024 * 1024).unwrap();
+    assert_eq!(store.block_count(), 3);
+    assert_eq!(segment_len(2), committed_len);
+
+    _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_file_store_prune() {
+    use crate::backend::params::REGTEST_DIFFICULTY;
+    use crate::backend::Transactions;
+
+    let dir = temp_store_dir("prune");
+    let blocks = (0..4)
+        .map(|idx| {
+            Block::mine_with(
+                idx,
+                Transactions::empty(),
+                "miner".to_string(),
+                REGTEST_DIFFICULTY,
+            )
+        })
+        .collect::<Vec<Block>>();
+
+    {
+        let mut store = FileStore::open_with_segment_size(&dir, 1).unwrap();
+        for (height, block) in blocks.iter().enumerate() {
+            let mut batch = WriteBatch::new();
+            batch.put_block(height as u64, block.clone());
+            store.write(batch).unwrap();
+        }
+
+        store.prune_bodies(2).unwrap();
+        assert!(!dir.join("segment-00001.log").exists());
+        assert!(dir.join("segment-00002.log").exists());
+    }
+
+    let store = FileStore::open_with_segment_size(&dir, 1).unwrap();
+    assert_eq!(store.pruned_below(), 2);
+    assert!(matches!(
+        store.block_by_height(0),
+        Err(StoreError::Pruned(0))
+    ));
+    assert_eq!(
+        store.header_by_height(1).unwrap().unwrap(),
+        blocks[1].header()
+    );
+    assert!(store
+        .block_by_hash(blocks[3].current_hash())
+        .unwrap()
+        .is_some());
+
+    _ = fs::remove_dir_all(&dir);
+}
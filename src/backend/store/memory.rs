@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::backend::{
+    enums::StoreError,
+    store::chainstore::{check_heights, BatchOp, ChainStore, WriteBatch},
+    transaction::valuestore::StateSnapshot,
+    types::{Hash, StateEntry},
+    Block, BlockHeader,
+};
+
+// keeps everything in memory. contents are lost when dropped.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    headers: Vec<BlockHeader>,
+    // `None` once pruned.
+    blocks: Vec<Option<Block>>,
+    heights: HashMap<Hash, u64>,
+    pruned_below: u64,
+    state: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore {
+            headers: vec![],
+            blocks: vec![],
+            heights: HashMap::new(),
+            pruned_below: 0,
+            state: BTreeMap::new(),
+        }
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn height_of(&self, hash: &Hash) -> Option<u64> {
+        self.heights.get(hash).copied()
+    }
+
+    fn block_by_height(&self, height: u64) -> Result<Option<Block>, StoreError> {
+        match self.blocks.get(height as usize) {
+            Some(Some(block)) => Ok(Some(block.clone())),
+            Some(None) => Err(StoreError::Pruned(height)),
+            None => Ok(None),
+        }
+    }
+
+    fn header_by_height(&self, height: u64) -> Result<Option<BlockHeader>, StoreError> {
+        Ok(self.headers.get(height as usize).cloned())
+    }
+
+    fn block_count(&self) -> u64 {
+        self.headers.len() as u64
+    }
+
+    fn state(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(self.state.get(key).cloned())
+    }
+
+    fn state_entries(&self) -> Result<Vec<StateEntry>, StoreError> {
+        Ok(self
+            .state
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError> {
+        // validate up front so a failing batch leaves nothing behind.
+        check_heights(&batch, self.block_count())?;
+
+        for op in batch.into_ops() {
+            match op {
+                BatchOp::PutBlock { height, block } => {
+                    let height_idx = height as usize;
+                    let header = block.header();
+
+                    if height_idx == self.headers.len() {
+                        self.headers.push(header);
+                        self.blocks.push(Some(block));
+                    } else {
+                        let replaced = std::mem::replace(&mut self.headers[height_idx], header);
+                        self.heights.remove(replaced.current_hash());
+                        self.blocks[height_idx] = Some(block);
+                    }
+
+                    self.heights
+                        .insert(self.headers[height_idx].current_hash_str(), height);
+                }
+                BatchOp::PutState { key, value } => {
+                    self.state.insert(key, value);
+                }
+                BatchOp::DeleteState { key } => {
+                    self.state.remove(&key);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // nothing to do, the state is never lost.
+    fn put_snapshot(&mut self, _snapshot: StateSnapshot) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    // the state is always current, so it serves as the snapshot of the tip.
+    fn latest_snapshot(&self) -> Result<Option<StateSnapshot>, StoreError> {
+        let last = match self.headers.last() {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        Ok(Some(StateSnapshot::new(
+            self.block_count() - 1,
+            last.current_hash_str(),
+            self.state_entries()?,
+        )))
+    }
+
+    fn latest_snapshot_height(&self) -> Option<u64> {
+        self.block_count().checked_sub(1)
+    }
+
+    fn prune_bodies(&mut self, height: u64) -> Result<(), StoreError> {
+        let below = height.min(self.block_count());
+
+        for body in self.blocks.iter_mut().take(below as usize) {
+            *body = None;
+        }
+        self.pruned_below = self.pruned_below.max(below);
+
+        Ok(())
+    }
+
+    fn pruned_below(&self) -> u64 {
+        self.pruned_below
+    }
+}
+
+#[test]
+fn test_memory_store_batch() {
+    use crate::backend::params::REGTEST_DIFFICULTY;
+    use crate::backend::Transactions;
+
+    let mut store = MemoryStore::new();
+    let block = Block::mine_with(
+        0,
+        Transactions::empty(),
+        "miner".to_string(),
+        REGTEST_DIFFICULTY,
+    );
+    let hash = block.current_hash_str();
+
+    let mut batch = WriteBatch::new();
+    batch.put_block(0, block);
+    batch.put_state(b"key".to_vec(), vec![1]);
+    store.write(batch).unwrap();
+
+    assert_eq!(store.block_count(), 1);
+    assert_eq!(store.block_by_hash(&hash).unwrap().unwrap().index(), 0);
+    assert_eq!(store.state(b"key").unwrap(), Some(vec![1]));
+
+    // a gap in heights rejects the whole batch.
+    let mut batch = WriteBatch::new();
+    batch.delete_state(b"key".to_vec());
+    batch.put_block(
+        5,
+        Block::mine_with(
+            5,
+            Transactions::empty(),
+            "miner".to_string(),
+            REGTEST_DIFFICULTY,
+        ),
+    );
+    assert!(store.write(batch).is_err());
+    assert_eq!(store.state(b"key").unwrap(), Some(vec![1]));
+}
+
+#[test]
+fn test_memory_store_prune() {
+    use crate::backend::params::REGTEST_DIFFICULTY;
+    use crate::backend::Transactions;
+
+    let mut store = MemoryStore::new();
+    let mut batch = WriteBatch::new();
+    for height in 0..3 {
+        batch.put_block(
+            height,
+            Block::mine_with(
+                height,
+                Transactions::empty(),
+                "miner".to_string(),
+                REGTEST_DIFFICULTY,
+            ),
+        );
+    }
+    store.write(batch).unwrap();
+
+    store.prune_bodies(2).unwrap();
+
+    assert!(matches!(
+        store.block_by_height(1),
+        Err(StoreError::Pruned(1))
+    ));
+    assert_eq!(store.header_by_height(1).unwrap().unwrap().index(), 1);
+    assert!(store.block_by_height(2).unwrap().is_some());
+    assert_eq!(store.pruned_below(), 2);
+}
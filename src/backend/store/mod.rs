@@ -0,0 +1,3 @@
+pub mod chainstore;
+pub mod file;
+pub mod memory;
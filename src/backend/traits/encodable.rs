@@ -0,0 +1,110 @@
+use crate::backend::enums::DecodeError;
+
+// canonical byte encoding used for persisting chain data.
+// unlike `Hashable::bytes`, everything needed to rebuild the item is included.
+pub trait Encodable: Sized {
+    fn encode(&self) -> Vec<u8>;
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError>;
+
+    // decode a value which must take up the whole slice.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = ByteReader::new(bytes);
+        let item = Self::decode(&mut reader)?;
+
+        if !reader.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+
+        Ok(item)
+    }
+}
+
+// length prefixed byte string.
+pub fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend((bytes.len() as u32).to_le_bytes());
+    out.extend(bytes);
+}
+
+pub fn put_str(out: &mut Vec<u8>, text: &str) {
+    put_bytes(out, text.as_bytes());
+}
+
+pub fn put_opt_str(out: &mut Vec<u8>, text: &Option<String>) {
+    match text {
+        Some(text) => {
+            out.push(1);
+            put_str(out, text);
+        }
+        None => out.push(0),
+    }
+}
+
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        ByteReader { bytes, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        let mut arr = [0_u8; 4];
+        arr.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(arr))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut arr = [0_u8; 8];
+        arr.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(arr))
+    }
+
+    pub fn u128(&mut self) -> Result<u128, DecodeError> {
+        let mut arr = [0_u8; 16];
+        arr.copy_from_slice(self.take(16)?);
+        Ok(u128::from_le_bytes(arr))
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, DecodeError> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub fn string(&mut self) -> Result<String, DecodeError> {
+        String::from_utf8(self.bytes()?).map_err(|_| DecodeError::InvalidData)
+    }
+
+    pub fn opt_string(&mut self) -> Result<Option<String>, DecodeError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.string()?)),
+            _ => Err(DecodeError::InvalidData),
+        }
+    }
+}
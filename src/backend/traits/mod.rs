@@ -1 +1,3 @@
+pub mod clock;
+pub mod encodable;
 pub mod hashable;
\ No newline at end of file
@@ -0,0 +1,118 @@
+use crypto::{digest::Digest, sha2::Sha256};
+
+use crate::backend::{
+    enums::{BlockValidationError, DecodeError},
+    traits::encodable::{put_str, ByteReader},
+    types::{AssetId, Hash},
+    Encodable, Transaction, TransactionKind,
+};
+
+// asset id of the coin blocks are rewarded and fees are paid in.
+pub const NATIVE_ASSET: &str = "";
+pub const MAX_SYMBOL_LEN: usize = 12;
+pub const MAX_DECIMALS: u8 = 18;
+
+// fungible asset issued on the chain. the whole supply goes to the issuer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    symbol: String,
+    // digits of the amounts wallets show after the decimal point. amounts themselves are integers.
+    decimals: u8,
+    supply: u64,
+    issuer: Hash,
+}
+
+impl Token {
+    pub fn new(symbol: String, decimals: u8, supply: u64, issuer: Hash) -> Self {
+        Token {
+            symbol,
+            decimals,
+            supply,
+            issuer,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    pub fn supply(&self) -> u64 {
+        self.supply
+    }
+
+    pub fn issuer(&self) -> &Hash {
+        &self.issuer
+    }
+
+    // an alphanumeric symbol of at most `MAX_SYMBOL_LEN` characters, and something to issue.
+    pub fn check(&self) -> Result<(), BlockValidationError> {
+        let valid_symbol = !self.symbol.is_empty()
+            && self.symbol.len() <= MAX_SYMBOL_LEN
+            && self.symbol.chars().all(|c| c.is_ascii_alphanumeric());
+
+        if !valid_symbol || self.decimals > MAX_DECIMALS || self.supply == 0 {
+            return Err(BlockValidationError::InvalidToken);
+        }
+
+        Ok(())
+    }
+
+    // the token an issue transaction creates. the issue itself moves nothing but the fee.
+    pub fn from_issue(tx: &Transaction) -> Result<Self, BlockValidationError> {
+        let token = match tx.kind() {
+            TransactionKind::Issue {
+                symbol,
+                decimals,
+                supply,
+            } => Token::new(
+                symbol.clone(),
+                *decimals,
+                *supply,
+                tx.from_address().clone(),
+            ),
+            _ => return Err(BlockValidationError::InvalidInput),
+        };
+        token.check()?;
+
+        if *tx.value() != 0 || !tx.is_native() {
+            return Err(BlockValidationError::InvalidToken);
+        }
+
+        Ok(token)
+    }
+}
+
+// id of the asset issued by the transaction `tx_id`.
+pub fn asset_id(tx_id: &Hash) -> AssetId {
+    let mut hasher = Sha256::new();
+    hasher.input(b"asset/");
+    hasher.input(tx_id.as_bytes());
+
+    hasher.result_str()
+}
+
+impl Encodable for Token {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        put_str(&mut bytes, &self.symbol);
+        bytes.push(self.decimals);
+        bytes.extend(self.supply.to_le_bytes());
+        put_str(&mut bytes, &self.issuer);
+
+        bytes
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        Ok(Token {
+            symbol: reader.string()?,
+            decimals: reader.u8()?,
+            supply: reader.u64()?,
+            issuer: reader.string()?,
+        })
+    }
+}
@@ -0,0 +1,327 @@
+use crypto::{digest::Digest, sha2::Sha256};
+
+use crate::backend::{
+    enums::DecodeError,
+    functions::{hex_bytes, hex_str},
+    types::Hash,
+};
+
+use super::{
+    script::{Op, Script},
+    transaction::Transaction,
+};
+
+// hash time-locked contract. funds at its address go to the receiver, if they reveal the
+// preimage of the hash lock below the timeout height, or back to the sender from then on.
+// both sides of a swap lock to the same hash, and claiming one side reveals the preimage
+// for the other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Htlc {
+    // sha256 of the preimage.
+    hash_lock: [u8; 32],
+    // ed25519 public keys.
+    receiver: [u8; 32],
+    sender: [u8; 32],
+    timeout: u64,
+}
+
+impl Htlc {
+    // the hash lock and both keys are given in hex, and have to be 32 bytes each.
+    pub fn new(
+        hash_lock: &str,
+        receiver: &str,
+        sender: &str,
+        timeout: u64,
+    ) -> Result<Self, DecodeError> {
+        Ok(Htlc {
+            hash_lock: hex_array(hash_lock)?,
+            receiver: hex_array(receiver)?,
+            sender: hex_array(sender)?,
+            timeout,
+        })
+    }
+
+    pub fn hash_lock(&self) -> Hash {
+        hex_str(&self.hash_lock)
+    }
+
+    pub fn receiver(&self) -> Hash {
+        hex_str(&self.receiver)
+    }
+
+    pub fn sender(&self) -> Hash {
+        hex_str(&self.sender)
+    }
+
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+
+    // claim with `signature preimage 1`, refund with `signature <empty>`.
+    pub fn script(&self) -> Script {
+        Script::new(vec![
+            Op::If,
+            Op::Number(self.timeout),
+            Op::CheckHeightBefore,
+            Op::Sha256,
+            Op::Push(self.hash_lock.to_vec()),
+            Op::EqualVerify,
+            Op::Push(self.receiver.to_vec()),
+            Op::CheckSig,
+            Op::Else,
+            Op::Number(self.timeout),
+            Op::CheckHeight,
+            Op::Push(self.sender.to_vec()),
+            Op::CheckSig,
+            Op::EndIf,
+        ])
+    }
+
+    // where the funds are locked.
+    pub fn address(&self) -> Hash {
+        self.script().address()
+    }
+
+    // spend `tx`, sent from the contract address, as the receiver.
+    pub fn claim(&self, tx: Transaction, secret_key: &[u8; 64], preimage: &[u8]) -> Transaction {
+        let signature = tx.script_signature(secret_key);
+
+        tx.with_script(self.script())
+            .with_witness(vec![signature, preimage.to_vec(), vec![1]])
+    }
+
+    // spend `tx`, sent from the contract address, as the sender once the contract timed out.
+    pub fn refund(&self, tx: Transaction, secret_key: &[u8; 64]) -> Transaction {
+        let signature = tx.script_signature(secret_key);
+
+        tx.with_script(self.script())
+            .with_witness(vec![signature, vec![]])
+    }
+
+    // the preimage a claim of this contract revealed.
+    pub fn revealed_preimage(&self, tx: &Transaction) -> Option<Vec<u8>> {
+        if tx.script() != Some(&self.script()) {
+            return None;
+        }
+
+        tx.witness()
+            .get(1)
+            .filter(|preimage| hash_lock(preimage) == self.hash_lock())
+            .cloned()
+    }
+}
+
+// the hash lock for `preimage`.
+pub fn hash_lock(preimage: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.input(preimage);
+    hasher.result_str()
+}
+
+fn hex_array(hex: &str) -> Result<[u8; 32], DecodeError> {
+    hex_bytes(hex)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(DecodeError::InvalidData)
+}
+
+// mine `transactions` on top of the tip.
+#[cfg(test)]
+fn mine_helper(
+    blk_chain: &mut crate::backend::BlockChain,
+    transactions: Vec<Transaction>,
+) -> Result<(), crate::backend::BlockValidationError> {
+    use crate::backend::{Block, Transactions};
+
+    let mut block_transactions = Transactions::empty();
+    block_transactions.values_mut().extend(transactions);
+    let block = Block::mine_on(
+        &blk_chain.last_header().unwrap(),
+        block_transactions,
+        "miner".to_string(),
+    );
+    let block = blk_chain.seal_block(block)?;
+
+    blk_chain.update_block(block).map(|_| ())
+}
+
+#[test]
+fn test_htlc_swap() {
+    use crate::backend::{
+        functions::address_of, store::file::funded_chain, BlockValidationError, ChainParams,
+        ScriptError,
+    };
+    use crypto::ed25519;
+
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let (bob, bob_public) = ed25519::keypair(&[2; 32]);
+
+    // alice trades 50 coins of chain a for 30 coins bob has on chain b.
+    let mut chain_a = funded_chain(ChainParams::regtest(), &[(address_of(&alice_public), 100)]);
+    let mut chain_b = funded_chain(ChainParams::regtest(), &[(address_of(&bob_public), 100)]);
+
+    // only alice knows the secret. her contract runs longer than bob's, so she can't
+    // wait to claim his coins until after she could take back hers.
+    let secret = b"alice's secret".to_vec();
+    let on_a = Htlc::new(
+        &hash_lock(&secret),
+        &hex_str(&bob_public),
+        &hex_str(&alice_public),
+        10,
+    )
+    .unwrap();
+    mine_helper(
+        &mut chain_a,
+        vec![Transaction::signed(&alice, on_a.address(), 50, 0, 1)],
+    )
+    .unwrap();
+
+    // bob checks alice's lock on chain a before locking to the same hash on chain b.
+    assert_eq!(chain_a.value_store().balance(&on_a.address()), Some(50));
+    let on_b = Htlc::new(
+        &on_a.hash_lock(),
+        &hex_str(&alice_public),
+        &hex_str(&bob_public),
+        5,
+    )
+    .unwrap();
+    mine_helper(
+        &mut chain_b,
+        vec![Transaction::signed(&bob, on_b.address(), 30, 0, 1)],
+    )
+    .unwrap();
+
+    // neither side can be refunded before its timeout.
+    let refund = on_a.refund(
+        Transaction::new(on_a.address(), address_of(&alice_public), 50),
+        &alice,
+    );
+    assert!(matches!(
+        mine_helper(&mut chain_a, vec![refund]),
+        Err(BlockValidationError::ScriptFailed(ScriptError::Locked))
+    ));
+
+    // a wrong preimage doesn't open the lock.
+    let spend_b = Transaction::new(on_b.address(), address_of(&alice_public), 30);
+    let guess = on_b.claim(spend_b.clone(), &alice, b"guess");
+    assert!(matches!(
+        mine_helper(&mut chain_b, vec![guess]),
+        Err(BlockValidationError::ScriptFailed(
+            ScriptError::VerifyFailed
+        ))
+    ));
+
+    // alice claims on chain b, which reveals the secret.
+    let claim_b = on_b.claim(spend_b, &alice, &secret);
+    mine_helper(&mut chain_b, vec![claim_b]).unwrap();
+    assert_eq!(
+        chain_b.value_store().balance(&address_of(&alice_public)),
+        Some(30)
+    );
+
+    // bob reads it from the block on chain b and claims on chain a.
+    let revealed = chain_b
+        .last()
+        .unwrap()
+        .transactions()
+        .values()
+        .iter()
+        .find_map(|tx| on_b.revealed_preimage(tx))
+        .unwrap();
+    let claim_a = on_a.claim(
+        Transaction::new(on_a.address(), address_of(&bob_public), 50),
+        &bob,
+        &revealed,
+    );
+    mine_helper(&mut chain_a, vec![claim_a]).unwrap();
+
+    assert_eq!(
+        chain_a.value_store().balance(&address_of(&bob_public)),
+        Some(50)
+    );
+    assert_eq!(
+        chain_a.value_store().balance(&address_of(&alice_public)),
+        Some(49)
+    );
+    assert_eq!(
+        chain_b.value_store().balance(&address_of(&bob_public)),
+        Some(69)
+    );
+}
+
+#[test]
+fn test_htlc_refund() {
+    use crate::backend::{
+        functions::address_of, store::file::funded_chain, BlockValidationError, ChainParams,
+        ScriptError,
+    };
+    use crypto::ed25519;
+
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let (bob, bob_public) = ed25519::keypair(&[2; 32]);
+    let mut blk_chain = funded_chain(ChainParams::regtest(), &[(address_of(&alice_public), 100)]);
+
+    let secret = b"alice's secret".to_vec();
+    let htlc = Htlc::new(
+        &hash_lock(&secret),
+        &hex_str(&bob_public),
+        &hex_str(&alice_public),
+        3,
+    )
+    .unwrap();
+
+    // a lock or key which isn't 32 bytes of hex can't go into the script.
+    assert!(matches!(
+        Htlc::new("not hex", &hex_str(&bob_public), &hex_str(&alice_public), 3),
+        Err(DecodeError::InvalidData)
+    ));
+    assert!(matches!(
+        Htlc::new(
+            &hash_lock(&secret),
+            &hex_str(&bob_public[..31]),
+            &hex_str(&alice_public),
+            3
+        ),
+        Err(DecodeError::InvalidData)
+    ));
+    mine_helper(
+        &mut blk_chain,
+        vec![Transaction::signed(&alice, htlc.address(), 50, 0, 1)],
+    )
+    .unwrap();
+    mine_helper(&mut blk_chain, vec![]).unwrap();
+
+    // from the timeout on, the preimage is no use to the receiver.
+    let claim = htlc.claim(
+        Transaction::new(htlc.address(), address_of(&bob_public), 50),
+        &bob,
+        &secret,
+    );
+    assert!(matches!(
+        mine_helper(&mut blk_chain, vec![claim]),
+        Err(BlockValidationError::ScriptFailed(ScriptError::Expired))
+    ));
+
+    // the lock holds whatever the spend carries.
+    let spend = Transaction::new(htlc.address(), address_of(&alice_public), 50);
+    assert!(matches!(
+        mine_helper(&mut blk_chain, vec![spend.clone()]),
+        Err(BlockValidationError::UnsignedTransaction)
+    ));
+    assert!(matches!(
+        mine_helper(&mut blk_chain, vec![spend.clone().sign(&alice)]),
+        Err(BlockValidationError::InvalidSignature)
+    ));
+
+    // only the sender's key refunds.
+    let stolen = htlc.refund(spend.clone(), &bob);
+    assert!(matches!(
+        mine_helper(&mut blk_chain, vec![stolen]),
+        Err(BlockValidationError::ScriptFailed(ScriptError::Failed))
+    ));
+
+    mine_helper(&mut blk_chain, vec![htlc.refund(spend, &alice)]).unwrap();
+    assert_eq!(
+        blk_chain.value_store().balance(&address_of(&alice_public)),
+        Some(99)
+    );
+}
@@ -0,0 +1,662 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::backend::{
+    enums::MempoolError, params::ChainParams, types::Hash, ChainUpdate, Token, Transaction,
+    TransactionKind, ValueStore,
+};
+
+// how many transactions are kept by default.
+pub const DEFAULT_MAX_MEMPOOL_TXS: usize = 5_000;
+
+// how much more a transaction has to pay to replace a pending one with the same nonce.
+// both bumps have to be met.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplacementRules {
+    min_bump_percent: u64,
+    min_bump: u64,
+}
+
+impl Default for ReplacementRules {
+    fn default() -> Self {
+        Self::new(10, 1)
+    }
+}
+
+impl ReplacementRules {
+    pub fn new(min_bump_percent: u64, min_bump: u64) -> Self {
+        ReplacementRules {
+            min_bump_percent,
+            min_bump,
+        }
+    }
+
+    pub fn min_bump_percent(&self) -> u64 {
+        self.min_bump_percent
+    }
+
+    pub fn min_bump(&self) -> u64 {
+        self.min_bump
+    }
+
+    // lowest fee a replacement of a transaction paying `fee` may offer.
+    pub fn required_fee(&self, fee: u64) -> u64 {
+        let bumped = (fee as u128 * (100 + self.min_bump_percent as u128)).div_ceil(100);
+
+        (bumped.min(u64::MAX as u128) as u64).max(fee.saturating_add(self.min_bump))
+    }
+}
+
+// pending transactions which left the pool for another one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MempoolEvent {
+    // replaced by a transaction with the same sender and nonce.
+    Replaced { old: Hash, new: Hash },
+    // dropped to make room in a full pool.
+    Evicted(Hash),
+    // past its validity window, or following a transaction which is.
+    Expired(Hash),
+}
+
+#[derive(Debug, Clone)]
+struct PoolEntry {
+    tx: Transaction,
+    size: usize,
+    // order of arrival, older entries win fee rate ties.
+    seq: u64,
+}
+
+impl PoolEntry {
+    // higher fee per byte first. compared crosswise so no rate is rounded.
+    fn priority(&self, other: &PoolEntry) -> Ordering {
+        let rate = self.tx.fee() as u128 * other.size as u128;
+        let other_rate = other.tx.fee() as u128 * self.size as u128;
+
+        other_rate.cmp(&rate).then_with(|| self.seq.cmp(&other.seq))
+    }
+}
+
+// signed transactions waiting for a block, checked against the state of the active chain.
+#[derive(Debug)]
+pub struct Mempool {
+    entries: HashMap<Hash, PoolEntry>,
+    max_size: usize,
+    // limits transactions are held to.
+    params: ChainParams,
+    next_seq: u64,
+    replacement_rules: ReplacementRules,
+    events: Vec<MempoolEvent>,
+    // height and time of the tip of the active chain.
+    tip_height: u64,
+    tip_timestamp: u64,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_MEMPOOL_TXS, &ChainParams::default())
+    }
+}
+
+impl Mempool {
+    pub fn new(max_size: usize, params: &ChainParams) -> Self {
+        Mempool {
+            entries: HashMap::new(),
+            max_size,
+            params: params.clone(),
+            next_seq: 0,
+            replacement_rules: ReplacementRules::default(),
+            events: vec![],
+            tip_height: 0,
+            tip_timestamp: 0,
+        }
+    }
+
+    pub fn with_replacement_rules(mut self, rules: ReplacementRules) -> Self {
+        self.replacement_rules = rules;
+        self
+    }
+
+    pub fn replacement_rules(&self) -> &ReplacementRules {
+        &self.replacement_rules
+    }
+
+    // where the active chain stands, for a pool started on a chain past its genesis block.
+    // `update` keeps it current.
+    pub fn set_tip(&mut self, height: u64, timestamp: u64) {
+        self.tip_height = height;
+        self.tip_timestamp = timestamp;
+    }
+
+    // events since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<MempoolEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, id: &Hash) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    pub fn get(&self, id: &Hash) -> Option<&Transaction> {
+        self.entries.get(id).map(|entry| &entry.tx)
+    }
+
+    // every transaction, best fee rate first.
+    pub fn transactions(&self) -> Vec<&Transaction> {
+        let mut entries = self.entries.values().collect::<Vec<&PoolEntry>>();
+        entries.sort_by(|a, b| a.priority(b));
+
+        entries.into_iter().map(|entry| &entry.tx).collect()
+    }
+
+    // pending transactions of `address`, by nonce.
+    pub fn transactions_of(&self, address: &Hash) -> Vec<&Transaction> {
+        let mut txs = self
+            .entries
+            .values()
+            .map(|entry| &entry.tx)
+            .filter(|tx| tx.from_address() == address)
+            .collect::<Vec<&Transaction>>();
+        txs.sort_by_key(|tx| tx.nonce());
+
+        txs
+    }
+
+    // admit a transaction which could follow the pending ones of its sender, or replace
+    // the pending one with the same nonce if it pays enough more.
+    // when full, the entry paying the least per byte makes room, if it pays less than `tx`.
+    pub fn add(&mut self, tx: Transaction, state: &ValueStore) -> Result<Hash, MempoolError> {
+        let id = tx.id();
+        if self.contains(&id) {
+            return Err(MempoolError::Duplicate);
+        }
+
+        let size = tx.size();
+        if size > self.params.max_tx_size() {
+            return Err(MempoolError::TooLarge {
+                size,
+                max_tx_size: self.params.max_tx_size(),
+            });
+        }
+
+        if tx.data().len() > self.params.max_tx_data() {
+            return Err(MempoolError::DataTooLarge {
+                size: tx.data().len(),
+                max_tx_data: self.params.max_tx_data(),
+            });
+        }
+
+        let data_fee = self.params.data_fee(tx.data().len());
+        if tx.fee() < data_fee {
+            return Err(MempoolError::InsufficientDataFee {
+                fee: tx.fee(),
+                required: data_fee,
+            });
+        }
+
+        if !tx.is_signed() {
+            return Err(MempoolError::Unsigned);
+        }
+
+        if tx.check_payouts().is_err() {
+            return Err(MempoolError::InvalidBatch);
+        }
+
+        if !tx.verify_signature() {
+            return Err(MempoolError::InvalidSignature);
+        }
+
+        // the next block is at least as late as the tip.
+        tx.check_script(
+            self.tip_height + 1,
+            self.tip_timestamp,
+            self.params.max_script_steps(),
+        )
+        .map_err(MempoolError::ScriptFailed)?;
+
+        match tx.kind() {
+            TransactionKind::Issue { .. } => {
+                Token::from_issue(&tx).map_err(|_| MempoolError::InvalidToken)?;
+            }
+            TransactionKind::Mint(_)
+            | TransactionKind::TransferNft(_)
+            | TransactionKind::Burn(_)
+                if *tx.value() != 0 || !tx.is_native() =>
+            {
+                return Err(MempoolError::InvalidNft);
+            }
+            _ => {}
+        }
+
+        let sender = tx.from_address().clone();
+
+        // checked against the state only, so a token minted by a pending transaction
+        // can't be moved before the mint is mined.
+        if let TransactionKind::TransferNft(nft) | TransactionKind::Burn(nft) = tx.kind() {
+            if state.nft(nft).map(|nft| nft.owner()) != Some(&sender) {
+                return Err(MempoolError::NotNftOwner);
+            }
+        }
+
+        let pending = self.transactions_of(&sender);
+
+        let replaced = pending
+            .iter()
+            .find(|pending| pending.nonce() == tx.nonce())
+            .map(|pending| (pending.id(), pending.fee()));
+
+        match &replaced {
+            Some((_, fee)) => {
+                let required = self.replacement_rules.required_fee(*fee);
+                if tx.fee() < required {
+                    return Err(MempoolError::ReplacementUnderpriced {
+                        fee: tx.fee(),
+                        required,
+                    });
+                }
+            }
+            None => {
+                let expected = state.nonce(&sender) + pending.len() as u64;
+                if tx.nonce() != expected {
+                    return Err(MempoolError::MismatchedNonce {
+                        expected,
+                        found: tx.nonce(),
+                    });
+                }
+            }
+        }
+
+        // fees are always paid in the native coin, values in the asset of each transaction.
+        let spends = pending
+            .into_iter()
+            .filter(|pending| pending.nonce() != tx.nonce())
+            .chain([&tx])
+            .collect::<Vec<&Transaction>>();
+
+        let balance = state.balance(&sender).unwrap_or(0);
+        let required = spends
+            .iter()
+            .try_fold(0_u64, |sum, tx| {
+                let value = if tx.is_native() { *tx.value() } else { 0 };
+                sum.checked_add(value)?.checked_add(tx.fee())
+            })
+            .unwrap_or(u64::MAX);
+        if balance < required {
+            return Err(MempoolError::InsufficientBalance { balance, required });
+        }
+
+        if !tx.is_native() {
+            let balance = state.asset_balance(&sender, tx.asset()).unwrap_or(0);
+            let required = spends
+                .iter()
+                .filter(|pending| pending.asset() == tx.asset())
+                .try_fold(0_u64, |sum, tx| sum.checked_add(*tx.value()))
+                .unwrap_or(u64::MAX);
+            if balance < required {
+                return Err(MempoolError::InsufficientBalance { balance, required });
+            }
+        }
+
+        let entry = PoolEntry {
+            tx,
+            size,
+            seq: self.next_seq,
+        };
+
+        if let Some((old, _)) = replaced {
+            self.entries.remove(&old);
+            self.events.push(MempoolEvent::Replaced {
+                old,
+                new: id.clone(),
+            });
+        } else if self.len() >= self.max_size {
+            match self.eviction_candidate(&sender) {
+                Some(victim) if entry.priority(&self.entries[&victim]) == Ordering::Less => {
+                    self.entries.remove(&victim);
+                    self.events.push(MempoolEvent::Evicted(victim));
+                }
+                _ => return Err(MempoolError::FeeTooLow),
+            }
+        }
+
+        self.next_seq += 1;
+        self.entries.insert(id.clone(), entry);
+
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: &Hash) -> Option<Transaction> {
+        self.entries.remove(id).map(|entry| entry.tx)
+    }
+
+    // follow the active chain. transactions of connected blocks leave the pool, those of
+    // disconnected blocks come back, and whatever no longer fits the new state is dropped.
+    pub fn update(&mut self, update: &ChainUpdate, state: &ValueStore) {
+        let included = update
+            .connected()
+            .iter()
+            .flat_map(|block| block.transactions().values())
+            .map(|tx| tx.id())
+            .collect::<HashSet<Hash>>();
+
+        // transactions of disconnected blocks are older than anything still pending.
+        let mut candidates = update
+            .disconnected()
+            .iter()
+            .rev()
+            .flat_map(|block| block.transactions().values().iter().cloned())
+            .filter(|tx| tx.is_signed())
+            .map(|tx| (0, tx))
+            .collect::<Vec<(u64, Transaction)>>();
+        candidates.extend(
+            self.entries
+                .drain()
+                .map(|(_, entry)| (entry.seq + 1, entry.tx)),
+        );
+
+        // re-admitted by nonce, so every sender's transactions stay in sequence.
+        candidates.sort_by_key(|(seq, tx)| (tx.nonce(), *seq));
+        for (_, tx) in candidates {
+            if !included.contains(&tx.id()) {
+                _ = self.add(tx, state);
+            }
+        }
+
+        if let (Some(tip), Some(height)) = (update.connected().last(), update.tip_height()) {
+            self.set_tip(height, tip.timestamp());
+            self.remove_expired(height + 1, tip.timestamp());
+        }
+    }
+
+    // drop transactions no block at `height` or later than `timestamp` may contain,
+    // along with the later transactions of their senders.
+    pub fn remove_expired(&mut self, height: u64, timestamp: u64) {
+        let mut cutoff = HashMap::<Hash, u64>::new();
+        for entry in self.entries.values() {
+            if entry.tx.is_expired(height, timestamp) {
+                let nonce = cutoff
+                    .entry(entry.tx.from_address().clone())
+                    .or_insert(u64::MAX);
+                *nonce = entry.tx.nonce().min(*nonce);
+            }
+        }
+
+        let mut expired = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| {
+                cutoff
+                    .get(entry.tx.from_address())
+                    .is_some_and(|nonce| entry.tx.nonce() >= *nonce)
+            })
+            .map(|(id, entry)| (entry.seq, id.clone()))
+            .collect::<Vec<(u64, Hash)>>();
+        expired.sort();
+
+        for (_, id) in expired {
+            self.entries.remove(&id);
+            self.events.push(MempoolEvent::Expired(id));
+        }
+    }
+
+    // the entry paying the least per byte among the last pending transaction of each sender,
+    // so no remaining transaction loses its predecessor. `sender` itself is spared.
+    fn eviction_candidate(&self, sender: &Hash) -> Option<Hash> {
+        let mut last = HashMap::<&Hash, (&Hash, &PoolEntry)>::new();
+        for (id, entry) in self.entries.iter() {
+            let from = entry.tx.from_address();
+            if from == sender {
+                continue;
+            }
+
+            match last.get(from) {
+                Some((_, current)) if current.tx.nonce() > entry.tx.nonce() => {}
+                _ => {
+                    last.insert(from, (id, entry));
+                }
+            }
+        }
+
+        last.into_values()
+            .max_by(|(_, a), (_, b)| a.priority(b))
+            .map(|(id, _)| id.clone())
+    }
+}
+
+// state where every key starts out with 100.
+#[cfg(test)]
+fn funded_state(public_keys: &[[u8; 32]]) -> ValueStore {
+    use crate::backend::functions::address_of;
+    use crate::backend::store::file::funded_genesis;
+
+    let allocations = public_keys
+        .iter()
+        .map(|public_key| (address_of(public_key), 100))
+        .collect::<Vec<(Hash, u64)>>();
+
+    let mut state = ValueStore::new();
+    state
+        .insert_genesis_balance(&funded_genesis(&ChainParams::regtest(), &allocations))
+        .unwrap();
+    state.commit();
+
+    state
+}
+
+#[test]
+fn test_mempool_admission() {
+    use crypto::ed25519;
+
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let (bob, bob_public) = ed25519::keypair(&[2; 32]);
+    let (carol, carol_public) = ed25519::keypair(&[3; 32]);
+
+    let funded = funded_state(&[alice_public, bob_public, carol_public]);
+
+    let mut pool = Mempool::new(2, &ChainParams::regtest());
+    let first = Transaction::signed(&alice, "x".to_string(), 10, 0, 1);
+    pool.add(first.clone(), &funded).unwrap();
+
+    assert!(matches!(
+        pool.add(first.clone(), &funded),
+        Err(MempoolError::Duplicate)
+    ));
+    assert!(matches!(
+        pool.add(
+            Transaction::new("x".to_string(), "y".to_string(), 1),
+            &funded
+        ),
+        Err(MempoolError::Unsigned)
+    ));
+    assert!(matches!(
+        pool.add(
+            Transaction::signed(&alice, "x".to_string(), 10, 2, 1),
+            &funded
+        ),
+        Err(MempoolError::MismatchedNonce {
+            expected: 1,
+            found: 2
+        })
+    ));
+    assert!(matches!(
+        pool.add(
+            Transaction::signed(&alice, "x".to_string(), 90, 1, 1),
+            &funded
+        ),
+        Err(MempoolError::InsufficientBalance {
+            balance: 100,
+            required: 102
+        })
+    ));
+
+    // full: a better paying transaction pushes out the cheapest one of another sender.
+    let cheap = Transaction::signed(&bob, "x".to_string(), 10, 0, 1);
+    pool.add(cheap.clone(), &funded).unwrap();
+    assert!(matches!(
+        pool.add(
+            Transaction::signed(&carol, "x".to_string(), 10, 0, 0),
+            &funded
+        ),
+        Err(MempoolError::FeeTooLow)
+    ));
+    let rich = Transaction::signed(&carol, "x".to_string(), 10, 0, 50);
+    pool.add(rich.clone(), &funded).unwrap();
+    assert_eq!(pool.len(), 2);
+    assert!(!pool.contains(&cheap.id()));
+    assert_eq!(pool.transactions()[0].id(), rich.id());
+}
+
+#[test]
+fn test_mempool_checks_assets() {
+    use crate::backend::functions::address_of;
+    use crypto::ed25519;
+
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let alice_address = address_of(&alice_public);
+    let funded = funded_state(&[alice_public]);
+    let mut pool = Mempool::new(10, &ChainParams::regtest());
+
+    // an issue the chain would refuse doesn't wait in the pool.
+    let bad_symbol = Transaction::issue(alice_address.clone(), "NOT A SYMBOL".to_string(), 2, 1)
+        .with_fee(1)
+        .sign(&alice);
+    assert!(matches!(
+        pool.add(bad_symbol, &funded),
+        Err(MempoolError::InvalidToken)
+    ));
+    let paid_in_gold = Transaction::issue(alice_address.clone(), "GOLD".to_string(), 2, 1)
+        .with_asset("gold".to_string())
+        .with_fee(1)
+        .sign(&alice);
+    assert!(matches!(
+        pool.add(paid_in_gold, &funded),
+        Err(MempoolError::InvalidToken)
+    ));
+
+    let issue = Transaction::issue(alice_address.clone(), "GOLD".to_string(), 2, 1)
+        .with_fee(1)
+        .sign(&alice);
+    pool.add(issue, &funded).unwrap();
+
+    // nor does a non-fungible token transaction which moves more than the fee.
+    let mint = Transaction::mint(
+        alice_address.clone(),
+        alice_address.clone(),
+        "art".to_string(),
+    )
+    .with_nonce(1)
+    .with_fee(1);
+    assert!(matches!(
+        pool.add(
+            mint.clone().with_asset("gold".to_string()).sign(&alice),
+            &funded
+        ),
+        Err(MempoolError::InvalidNft)
+    ));
+    pool.add(mint.sign(&alice), &funded).unwrap();
+}
+
+#[test]
+fn test_mempool_follows_chain() {
+    use crate::backend::functions::address_of;
+    use crate::backend::store::file::funded_chain;
+    use crate::backend::{Block, Transactions};
+    use crypto::ed25519;
+
+    let params = ChainParams::regtest();
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+
+    let mut blk_chain = funded_chain(params.clone(), &[(address_of(&alice_public), 100)]);
+    let genesis = blk_chain.last_header().unwrap();
+
+    let mut pool = Mempool::new(10, &params);
+    let first = Transaction::signed(&alice, "bob".to_string(), 10, 0, 1);
+    let second = Transaction::signed(&alice, "bob".to_string(), 10, 1, 1);
+    pool.add(first.clone(), blk_chain.value_store()).unwrap();
+    pool.add(second.clone(), blk_chain.value_store()).unwrap();
+
+    // the first transaction is mined, the second one stays.
+    let mut transactions = Transactions::empty();
+    transactions.values_mut().push(first.clone());
+    let block = Block::mine_on(&genesis, transactions, "miner".to_string());
+    let block = blk_chain.seal_block(block).unwrap();
+    let update = blk_chain.update_block(block.clone()).unwrap();
+    pool.update(&update, blk_chain.value_store());
+    assert!(!pool.contains(&first.id()));
+    assert!(pool.contains(&second.id()));
+
+    // a heavier branch without it brings it back, ahead of the second one.
+    let fork = Block::mine_on(&genesis, Transactions::empty(), "other".to_string());
+    let fork = blk_chain.seal_block(fork).unwrap();
+    blk_chain.update_block(fork.clone()).unwrap();
+    let tip = Block::mine_on(&fork.header(), Transactions::empty(), "other".to_string());
+    let tip = blk_chain.seal_block(tip).unwrap();
+    let update = blk_chain.update_block(tip).unwrap();
+    assert!(update.is_reorg());
+
+    pool.update(&update, blk_chain.value_store());
+    assert_eq!(
+        pool.transactions_of(&address_of(&alice_public))
+            .iter()
+            .map(|tx| tx.id())
+            .collect::<Vec<Hash>>(),
+        vec![first.id(), second.id()]
+    );
+}
+
+#[test]
+fn test_replace_by_fee() {
+    use crypto::ed25519;
+
+    let (alice, alice_public) = ed25519::keypair(&[1; 32]);
+    let state = funded_state(&[alice_public]);
+
+    let mut pool = Mempool::new(10, &ChainParams::regtest())
+        .with_replacement_rules(ReplacementRules::new(50, 5));
+    let stuck = Transaction::signed(&alice, "bob".to_string(), 10, 0, 20);
+    let next = Transaction::signed(&alice, "bob".to_string(), 10, 1, 20);
+    pool.add(stuck.clone(), &state).unwrap();
+    pool.add(next.clone(), &state).unwrap();
+
+    // 50% more than 20 is needed, not just 5 more.
+    assert!(matches!(
+        pool.add(
+            Transaction::signed(&alice, "carol".to_string(), 10, 0, 29),
+            &state
+        ),
+        Err(MempoolError::ReplacementUnderpriced {
+            fee: 29,
+            required: 30
+        })
+    ));
+
+    // the replacement can't spend what the rest of the queue needs.
+    assert!(matches!(
+        pool.add(
+            Transaction::signed(&alice, "carol".to_string(), 50, 0, 30),
+            &state
+        ),
+        Err(MempoolError::InsufficientBalance { .. })
+    ));
+
+    let cancel = Transaction::cancel(&alice, 0, 30);
+    pool.add(cancel.clone(), &state).unwrap();
+    assert_eq!(pool.len(), 2);
+    assert!(!pool.contains(&stuck.id()));
+    assert!(pool.contains(&next.id()));
+    assert_eq!(
+        pool.take_events(),
+        vec![MempoolEvent::Replaced {
+            old: stuck.id(),
+            new: cancel.id()
+        }]
+    );
+    assert!(pool.take_events().is_empty());
+}
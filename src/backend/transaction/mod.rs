@@ -1,3 +1,11 @@
-pub mod transactions;
+pub mod asset;
+pub mod htlc;
+pub mod mempool;
+pub mod multisig;
+pub mod nft;
+pub mod script;
+pub mod statetree;
+#[allow(clippy::module_inception)]
 pub mod transaction;
+pub mod transactions;
 pub mod valuestore;
\ No newline at end of file
@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+
+use crypto::{digest::Digest, sha2::Sha256};
+
+use crate::backend::{
+    enums::{BlockValidationError, DecodeError},
+    functions::{hash_str_bytes, verify_hex_signature},
+    traits::encodable::{put_str, ByteReader},
+    types::Hash,
+    Encodable,
+};
+
+// public keys which may sign for a multisig address, and how many of them have to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultisigPolicy {
+    // hex ed25519 public keys, sorted so the order they are given in doesn't matter.
+    public_keys: Vec<Hash>,
+    threshold: u32,
+}
+
+impl MultisigPolicy {
+    pub fn new(mut public_keys: Vec<Hash>, threshold: u32) -> Self {
+        public_keys.sort();
+        public_keys.dedup();
+
+        MultisigPolicy {
+            public_keys,
+            threshold,
+        }
+    }
+
+    pub fn public_keys(&self) -> &Vec<Hash> {
+        &self.public_keys
+    }
+
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    // the address funds of the policy are kept at.
+    pub fn address(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.input(b"multisig/");
+        hasher.input(&self.threshold.to_le_bytes());
+        for public_key in self.public_keys.iter() {
+            hasher.input(&hash_str_bytes(public_key));
+        }
+
+        hasher.result_str()
+    }
+
+    // at least `threshold` distinct keys of the policy signed `message` for `address`.
+    // a signature by anyone else, or one which doesn't verify, spoils the lot.
+    pub fn check(
+        &self,
+        address: &Hash,
+        message: &[u8],
+        signatures: &[(Hash, Hash)],
+    ) -> Result<(), BlockValidationError> {
+        if self.threshold == 0
+            || self.threshold as usize > self.public_keys.len()
+            || self.address() != *address
+        {
+            return Err(BlockValidationError::InvalidSignature);
+        }
+
+        let mut signers = HashSet::new();
+        for (public_key, signature) in signatures {
+            if !self.public_keys.contains(public_key)
+                || !verify_hex_signature(message, public_key, signature)
+            {
+                return Err(BlockValidationError::InvalidSignature);
+            }
+
+            signers.insert(public_key);
+        }
+
+        if signers.len() < self.threshold as usize {
+            return Err(BlockValidationError::InsufficientSignatures {
+                found: signers.len(),
+                required: self.threshold as usize,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Encodable for MultisigPolicy {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        bytes.extend(self.threshold.to_le_bytes());
+        bytes.extend((self.public_keys.len() as u32).to_le_bytes());
+        for public_key in self.public_keys.iter() {
+            put_str(&mut bytes, public_key);
+        }
+
+        bytes
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        let threshold = reader.u32()?;
+        let count = reader.u32()?;
+
+        let mut public_keys = vec![];
+        for _ in 0..count {
+            public_keys.push(reader.string()?);
+        }
+
+        Ok(MultisigPolicy::new(public_keys, threshold))
+    }
+}
+
+#[test]
+fn test_multisig_spend() {
+    use crate::backend::{
+        functions::hex_str, store::file::funded_chain, traits::encodable::Encodable, Block,
+        ChainParams, Transaction, Transactions,
+    };
+    use crypto::ed25519;
+
+    let keys = [[1; 32], [2; 32], [3; 32]].map(|seed| ed25519::keypair(&seed));
+    let (outsider, _) = ed25519::keypair(&[4; 32]);
+    let policy = MultisigPolicy::new(keys.iter().map(|(_, public)| hex_str(public)).collect(), 2);
+
+    let mut blk_chain = funded_chain(ChainParams::mainnet(), &[(policy.address(), 100)]);
+
+    let spend = Transaction::new(policy.address(), "bob".to_string(), 40)
+        .with_fee(1)
+        .with_multisig(policy.clone());
+
+    // one key, even signing twice, isn't enough.
+    let once = spend.clone().cosign(&keys[0].0).cosign(&keys[0].0);
+    assert!(matches!(
+        once.check_signatures(),
+        Err(BlockValidationError::InsufficientSignatures {
+            found: 1,
+            required: 2
+        })
+    ));
+
+    let outsider_signed = spend.clone().cosign(&keys[0].0).cosign(&outsider);
+    assert!(matches!(
+        outsider_signed.check_signatures(),
+        Err(BlockValidationError::InvalidSignature)
+    ));
+
+    // without the signatures of the policy, nobody can move its funds.
+    let last = blk_chain.last_header().unwrap();
+    let unsigned = Transaction::new(policy.address(), "bob".to_string(), 40);
+    let mut transactions = Transactions::empty();
+    transactions.values_mut().push(unsigned);
+    let block = Block::mine_on(&last, transactions, "miner".to_string());
+    assert!(matches!(
+        blk_chain.update_block(block),
+        Err(BlockValidationError::UnsignedTransaction)
+    ));
+
+    let signed = spend.cosign(&keys[2].0).cosign(&keys[0].0);
+    let decoded = Transaction::from_bytes(&signed.encode()).unwrap();
+    assert!(decoded.check_signatures().is_ok());
+
+    let mut transactions = Transactions::empty();
+    transactions.values_mut().push(decoded);
+    let block = Block::mine_on(&last, transactions, "miner".to_string());
+    let block = blk_chain.seal_block(block).unwrap();
+    blk_chain.update_block(block).unwrap();
+    assert_eq!(blk_chain.value_store().balance(&policy.address()), Some(59));
+    assert_eq!(
+        blk_chain.value_store().balance(&"bob".to_string()),
+        Some(40)
+    );
+    assert_eq!(blk_chain.value_store().nonce(&policy.address()), 1);
+}
@@ -0,0 +1,73 @@
+use crypto::{digest::Digest, sha2::Sha256};
+
+use crate::backend::{
+    enums::DecodeError,
+    traits::encodable::{put_str, ByteReader},
+    types::{Hash, NftId},
+    Encodable,
+};
+
+// unique token. only its owner can hand it over or burn it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nft {
+    owner: Hash,
+    // hash of the metadata kept off chain, such as a document or an image.
+    metadata: Hash,
+    minter: Hash,
+}
+
+impl Nft {
+    pub fn new(owner: Hash, metadata: Hash, minter: Hash) -> Self {
+        Nft {
+            owner,
+            metadata,
+            minter,
+        }
+    }
+
+    pub fn with_owner(mut self, owner: Hash) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    pub fn owner(&self) -> &Hash {
+        &self.owner
+    }
+
+    pub fn metadata(&self) -> &Hash {
+        &self.metadata
+    }
+
+    pub fn minter(&self) -> &Hash {
+        &self.minter
+    }
+}
+
+// id of the token minted by the transaction `tx_id`.
+pub fn nft_id(tx_id: &Hash) -> NftId {
+    let mut hasher = Sha256::new();
+    hasher.input(b"nft/");
+    hasher.input(tx_id.as_bytes());
+
+    hasher.result_str()
+}
+
+impl Encodable for Nft {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        put_str(&mut bytes, &self.owner);
+        put_str(&mut bytes, &self.metadata);
+        put_str(&mut bytes, &self.minter);
+
+        bytes
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        Ok(Nft {
+            owner: reader.string()?,
+            metadata: reader.string()?,
+            minter: reader.string()?,
+        })
+    }
+}
@@ -0,0 +1,523 @@
+use crypto::{digest::Digest, ed25519, sha2::Sha256};
+
+use crate::backend::{
+    enums::{DecodeError, ScriptError},
+    traits::encodable::{put_bytes, ByteReader},
+    types::Hash,
+    Encodable,
+};
+
+// most items the stack may hold, witness included.
+pub const MAX_STACK_DEPTH: usize = 100;
+// longest item that may be pushed.
+pub const MAX_ITEM_LEN: usize = 520;
+
+// one instruction of a spending script. items are byte strings, numbers are
+// little endian and at most 8 bytes wide, and an item is true unless all of its bytes are 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Push(Vec<u8>),
+    Number(u64),
+    Dup,
+    Drop,
+    Swap,
+    Equal,
+    // fail unless the two top items are equal.
+    EqualVerify,
+    // fail unless the top item is true.
+    Verify,
+    Add,
+    // second item >= top item.
+    GreaterOrEqual,
+    If,
+    Else,
+    EndIf,
+    Sha256,
+    // pops a public key, then a signature of the transaction, and pushes whether it verifies.
+    // a signature that doesn't verify, or an empty one, pushes false instead of failing,
+    // so thresholds can add up the checks of several keys.
+    CheckSig,
+    CheckSigVerify,
+    // fail unless the block is at least at the height on top of the stack.
+    CheckHeight,
+    // fail unless the block timestamp is at least the one on top of the stack.
+    CheckTime,
+    // fail unless the block is below the height on top of the stack.
+    CheckHeightBefore,
+    // fail unless the block timestamp is before the one on top of the stack.
+    CheckTimeBefore,
+}
+
+// what a script is run against.
+pub struct ScriptContext<'a> {
+    // the signed bytes of the spending transaction.
+    message: &'a [u8],
+    height: u64,
+    timestamp: u64,
+}
+
+impl<'a> ScriptContext<'a> {
+    pub fn new(message: &'a [u8], height: u64, timestamp: u64) -> Self {
+        ScriptContext {
+            message,
+            height,
+            timestamp,
+        }
+    }
+}
+
+// spending condition of a script address. the spender reveals the script and a witness,
+// whose items are pushed in order before the script runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    ops: Vec<Op>,
+}
+
+impl Script {
+    pub fn new(ops: Vec<Op>) -> Self {
+        Script { ops }
+    }
+
+    pub fn ops(&self) -> &Vec<Op> {
+        &self.ops
+    }
+
+    // the address funds locked by the script are kept at.
+    pub fn address(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.input(b"script/");
+        hasher.input(&self.encode());
+
+        hasher.result_str()
+    }
+
+    // run the script on `witness`. it succeeds when it finishes within `max_steps`
+    // with true on top of the stack.
+    pub fn eval(
+        &self,
+        witness: &[Vec<u8>],
+        context: &ScriptContext,
+        max_steps: usize,
+    ) -> Result<(), ScriptError> {
+        let mut interpreter = Interpreter {
+            stack: vec![],
+            conditions: vec![],
+            steps: 0,
+        };
+
+        for item in witness {
+            interpreter.push(item.clone())?;
+        }
+
+        for op in self.ops.iter() {
+            interpreter.steps += 1;
+            if interpreter.steps > max_steps {
+                return Err(ScriptError::StepLimitExceeded);
+            }
+
+            interpreter.step(op, context)?;
+        }
+
+        if !interpreter.conditions.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+
+        match interpreter.stack.last() {
+            Some(item) if is_true(item) => Ok(()),
+            _ => Err(ScriptError::Failed),
+        }
+    }
+}
+
+struct Interpreter {
+    stack: Vec<Vec<u8>>,
+    // whether each open `If` branch runs.
+    conditions: Vec<bool>,
+    steps: usize,
+}
+
+impl Interpreter {
+    fn step(&mut self, op: &Op, context: &ScriptContext) -> Result<(), ScriptError> {
+        // only conditionals are looked at inside a branch that doesn't run.
+        let running = self.conditions.iter().all(|running| *running);
+
+        match op {
+            Op::If => {
+                let condition = running && is_true(&self.pop()?);
+                self.conditions.push(condition);
+            }
+            Op::Else => {
+                let outer = self.conditions.len().saturating_sub(1);
+                let outer_running = self.conditions[..outer].iter().all(|running| *running);
+                let condition = self
+                    .conditions
+                    .last_mut()
+                    .ok_or(ScriptError::UnbalancedConditional)?;
+                *condition = outer_running && !*condition;
+            }
+            Op::EndIf => {
+                self.conditions
+                    .pop()
+                    .ok_or(ScriptError::UnbalancedConditional)?;
+            }
+            _ if !running => {}
+            Op::Push(item) => self.push(item.clone())?,
+            Op::Number(number) => self.push(number.to_le_bytes().to_vec())?,
+            Op::Dup => {
+                let item = self
+                    .stack
+                    .last()
+                    .ok_or(ScriptError::StackUnderflow)?
+                    .clone();
+                self.push(item)?;
+            }
+            Op::Drop => {
+                self.pop()?;
+            }
+            Op::Swap => {
+                let top = self.pop()?;
+                let second = self.pop()?;
+                self.push(top)?;
+                self.push(second)?;
+            }
+            Op::Equal => {
+                let equal = self.pop()? == self.pop()?;
+                self.push_bool(equal)?;
+            }
+            Op::EqualVerify => {
+                if self.pop()? != self.pop()? {
+                    return Err(ScriptError::VerifyFailed);
+                }
+            }
+            Op::Verify => {
+                if !is_true(&self.pop()?) {
+                    return Err(ScriptError::VerifyFailed);
+                }
+            }
+            Op::Add => {
+                let top = self.pop_number()?;
+                let second = self.pop_number()?;
+                let sum = second.checked_add(top).ok_or(ScriptError::InvalidNumber)?;
+                self.push(sum.to_le_bytes().to_vec())?;
+            }
+            Op::GreaterOrEqual => {
+                let top = self.pop_number()?;
+                let second = self.pop_number()?;
+                self.push_bool(second >= top)?;
+            }
+            Op::Sha256 => {
+                let item = self.pop()?;
+                let mut hasher = Sha256::new();
+                hasher.input(&item);
+                let mut hash = vec![0; 32];
+                hasher.result(&mut hash);
+                self.push(hash)?;
+            }
+            Op::CheckSig => {
+                let valid = self.check_sig(context)?;
+                self.push_bool(valid)?;
+            }
+            Op::CheckSigVerify => {
+                if !self.check_sig(context)? {
+                    return Err(ScriptError::VerifyFailed);
+                }
+            }
+            Op::CheckHeight => {
+                if context.height < self.pop_number()? {
+                    return Err(ScriptError::Locked);
+                }
+            }
+            Op::CheckTime => {
+                if context.timestamp < self.pop_number()? {
+                    return Err(ScriptError::Locked);
+                }
+            }
+            Op::CheckHeightBefore => {
+                if context.height >= self.pop_number()? {
+                    return Err(ScriptError::Expired);
+                }
+            }
+            Op::CheckTimeBefore => {
+                if context.timestamp >= self.pop_number()? {
+                    return Err(ScriptError::Expired);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, item: Vec<u8>) -> Result<(), ScriptError> {
+        if item.len() > MAX_ITEM_LEN {
+            return Err(ScriptError::ItemTooLarge);
+        }
+        if self.stack.len() >= MAX_STACK_DEPTH {
+            return Err(ScriptError::StackOverflow);
+        }
+
+        self.stack.push(item);
+        Ok(())
+    }
+
+    fn push_bool(&mut self, value: bool) -> Result<(), ScriptError> {
+        self.push(vec![value as u8])
+    }
+
+    fn pop(&mut self) -> Result<Vec<u8>, ScriptError> {
+        self.stack.pop().ok_or(ScriptError::StackUnderflow)
+    }
+
+    fn pop_number(&mut self) -> Result<u64, ScriptError> {
+        let item = self.pop()?;
+        if item.len() > 8 {
+            return Err(ScriptError::InvalidNumber);
+        }
+
+        let mut bytes = [0_u8; 8];
+        bytes[..item.len()].copy_from_slice(&item);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn check_sig(&mut self, context: &ScriptContext) -> Result<bool, ScriptError> {
+        let public_key = self.pop()?;
+        let signature = self.pop()?;
+
+        Ok(public_key.len() == 32
+            && signature.len() == 64
+            && ed25519::verify(context.message, &public_key, &signature))
+    }
+}
+
+fn is_true(item: &[u8]) -> bool {
+    item.iter().any(|byte| *byte != 0)
+}
+
+impl Op {
+    fn code(&self) -> u8 {
+        match self {
+            Op::Push(_) => 0x00,
+            Op::Number(_) => 0x01,
+            Op::Dup => 0x10,
+            Op::Drop => 0x11,
+            Op::Swap => 0x12,
+            Op::Equal => 0x20,
+            Op::EqualVerify => 0x21,
+            Op::Verify => 0x22,
+            Op::Add => 0x30,
+            Op::GreaterOrEqual => 0x31,
+            Op::If => 0x40,
+            Op::Else => 0x41,
+            Op::EndIf => 0x42,
+            Op::Sha256 => 0x50,
+            Op::CheckSig => 0x60,
+            Op::CheckSigVerify => 0x61,
+            Op::CheckHeight => 0x70,
+            Op::CheckTime => 0x71,
+            Op::CheckHeightBefore => 0x72,
+            Op::CheckTimeBefore => 0x73,
+        }
+    }
+}
+
+impl Encodable for Script {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        bytes.extend((self.ops.len() as u32).to_le_bytes());
+        for op in self.ops.iter() {
+            bytes.push(op.code());
+            match op {
+                Op::Push(item) => put_bytes(&mut bytes, item),
+                Op::Number(number) => bytes.extend(number.to_le_bytes()),
+                _ => {}
+            }
+        }
+
+        bytes
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        let count = reader.u32()?;
+
+        let mut ops = vec![];
+        for _ in 0..count {
+            ops.push(match reader.u8()? {
+                0x00 => Op::Push(reader.bytes()?),
+                0x01 => Op::Number(reader.u64()?),
+                0x10 => Op::Dup,
+                0x11 => Op::Drop,
+                0x12 => Op::Swap,
+                0x20 => Op::Equal,
+                0x21 => Op::EqualVerify,
+                0x22 => Op::Verify,
+                0x30 => Op::Add,
+                0x31 => Op::GreaterOrEqual,
+                0x40 => Op::If,
+                0x41 => Op::Else,
+                0x42 => Op::EndIf,
+                0x50 => Op::Sha256,
+                0x60 => Op::CheckSig,
+                0x61 => Op::CheckSigVerify,
+                0x70 => Op::CheckHeight,
+                0x71 => Op::CheckTime,
+                0x72 => Op::CheckHeightBefore,
+                0x73 => Op::CheckTimeBefore,
+                _ => return Err(DecodeError::InvalidData),
+            });
+        }
+
+        Ok(Script { ops })
+    }
+}
+
+#[test]
+fn test_script_eval() {
+    let (secret_key, public_key) = ed25519::keypair(&[1; 32]);
+    let (other, _) = ed25519::keypair(&[2; 32]);
+    let message = b"spend".to_vec();
+    let signature = ed25519::signature(&message, &secret_key).to_vec();
+
+    let mut hasher = Sha256::new();
+    hasher.input(b"secret");
+    let mut hash = vec![0; 32];
+    hasher.result(&mut hash);
+
+    // the key holder after height 10, or anyone knowing the secret.
+    let script = Script::new(vec![
+        Op::If,
+        Op::Number(10),
+        Op::CheckHeight,
+        Op::Push(public_key.to_vec()),
+        Op::CheckSig,
+        Op::Else,
+        Op::Sha256,
+        Op::Push(hash),
+        Op::Equal,
+        Op::EndIf,
+    ]);
+    let at = |height| ScriptContext::new(&message, height, 0);
+
+    let by_key = [signature.clone(), vec![1]];
+    assert_eq!(script.eval(&by_key, &at(10), 100), Ok(()));
+    assert_eq!(script.eval(&by_key, &at(9), 100), Err(ScriptError::Locked));
+    let by_other = [ed25519::signature(&message, &other).to_vec(), vec![1]];
+    assert_eq!(
+        script.eval(&by_other, &at(10), 100),
+        Err(ScriptError::Failed)
+    );
+
+    assert_eq!(
+        script.eval(&[b"secret".to_vec(), vec![]], &at(0), 100),
+        Ok(())
+    );
+    assert_eq!(
+        script.eval(&[b"guess".to_vec(), vec![]], &at(0), 100),
+        Err(ScriptError::Failed)
+    );
+    assert_eq!(
+        script.eval(&[], &at(0), 100),
+        Err(ScriptError::StackUnderflow)
+    );
+    assert_eq!(
+        script.eval(&[b"secret".to_vec(), vec![]], &at(0), 5),
+        Err(ScriptError::StepLimitExceeded)
+    );
+
+    // endless growth is cut off by the stack, not only by the step limit.
+    let flood = Script::new(vec![Op::Number(1); MAX_STACK_DEPTH + 1]);
+    assert_eq!(
+        flood.eval(&[], &at(0), 1_000),
+        Err(ScriptError::StackOverflow)
+    );
+
+    assert_eq!(Script::from_bytes(&script.encode()), Ok(script));
+}
+
+#[test]
+fn test_script_threshold_spend() {
+    use crate::backend::{
+        store::file::funded_chain, Block, BlockValidationError, ChainParams, Mempool, MempoolError,
+        Transaction, Transactions,
+    };
+
+    // two signatures of three keys, added up.
+    let keys = [[1; 32], [2; 32], [3; 32]].map(|seed| ed25519::keypair(&seed));
+    let mut ops = vec![];
+    for (index, (_, public_key)) in keys.iter().enumerate() {
+        // the count so far goes below the next signature.
+        if index > 0 {
+            ops.push(Op::Swap);
+        }
+        ops.push(Op::Push(public_key.to_vec()));
+        ops.push(Op::CheckSig);
+        if index > 0 {
+            ops.push(Op::Add);
+        }
+    }
+    ops.extend([Op::Number(2), Op::GreaterOrEqual]);
+    let script = Script::new(ops);
+
+    let mut blk_chain = funded_chain(ChainParams::regtest(), &[(script.address(), 100)]);
+    let last = blk_chain.last_header().unwrap();
+
+    let spend =
+        Transaction::new(script.address(), "bob".to_string(), 40).with_script(script.clone());
+    // witness items are pushed in order, so the signature for the last key goes first.
+    let mine = |witness: Vec<Vec<u8>>| {
+        let mut transactions = Transactions::empty();
+        transactions
+            .values_mut()
+            .push(spend.clone().with_witness(witness));
+        Block::mine_on(&last, transactions, "miner".to_string())
+    };
+
+    // the funds only move through the script, so a spend has to carry it.
+    let block_with = |tx: Transaction| {
+        let mut transactions = Transactions::empty();
+        transactions.values_mut().push(tx);
+        Block::mine_on(&last, transactions, "miner".to_string())
+    };
+    let bare = Transaction::new(script.address(), "bob".to_string(), 40);
+    assert!(matches!(
+        blk_chain.update_block(block_with(bare.clone())),
+        Err(BlockValidationError::UnsignedTransaction)
+    ));
+    assert!(matches!(
+        blk_chain.update_block(block_with(bare.clone().sign(&keys[0].0))),
+        Err(BlockValidationError::InvalidSignature)
+    ));
+    let other = bare.with_script(Script::new(vec![Op::Number(1)]));
+    assert!(matches!(
+        blk_chain.update_block(block_with(other)),
+        Err(BlockValidationError::InvalidSignature)
+    ));
+
+    let one = vec![vec![], vec![], spend.script_signature(&keys[0].0)];
+    assert!(matches!(
+        blk_chain.update_block(mine(one.clone())),
+        Err(BlockValidationError::ScriptFailed(ScriptError::Failed))
+    ));
+    // the pool runs the script too, rather than relaying a spend no block can take.
+    let mut pool = Mempool::new(10, &ChainParams::regtest());
+    assert!(matches!(
+        pool.add(spend.clone().with_witness(one), blk_chain.value_store()),
+        Err(MempoolError::ScriptFailed(ScriptError::Failed))
+    ));
+
+    let two = vec![
+        spend.script_signature(&keys[2].0),
+        vec![],
+        spend.script_signature(&keys[0].0),
+    ];
+    pool.add(
+        spend.clone().with_witness(two.clone()),
+        blk_chain.value_store(),
+    )
+    .unwrap();
+    let block = blk_chain.seal_block(mine(two)).unwrap();
+    blk_chain.update_block(block).unwrap();
+    assert_eq!(
+        blk_chain.value_store().balance(&"bob".to_string()),
+        Some(40)
+    );
+}
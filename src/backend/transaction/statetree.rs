@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use crypto::{digest::Digest, sha2::Sha256};
+
+use crate::backend::{functions::hex_str, traits::encodable::put_bytes, types::Hash};
+
+// bits of the path to a leaf, the sha256 of its key.
+const DEPTH: usize = 256;
+
+// sparse merkle tree over the state entries. every possible key has a leaf at the path of
+// its hash, so a change only rehashes the nodes above one leaf, and the root doesn't depend
+// on the order entries were written in.
+#[derive(Debug)]
+pub struct StateTree {
+    // nodes which aren't empty, by height above the leaves and path with the bits below
+    // that height cleared.
+    nodes: HashMap<(usize, [u8; 32]), [u8; 32]>,
+    // hash of an empty subtree of every height.
+    empty: Vec<[u8; 32]>,
+}
+
+impl StateTree {
+    pub fn new() -> Self {
+        let mut empty = vec![[0; 32]];
+        for height in 0..DEPTH {
+            empty.push(node_hash(&empty[height], &empty[height]));
+        }
+
+        StateTree {
+            nodes: HashMap::new(),
+            empty,
+        }
+    }
+
+    // set the leaf of `key`, or clear it if `value` is `None`.
+    pub fn update(&mut self, key: &[u8], value: Option<&[u8]>) {
+        let mut path = [0; 32];
+        let mut hasher = Sha256::new();
+        hasher.input(key);
+        hasher.result(&mut path);
+
+        let mut hash = match value {
+            Some(value) => leaf_hash(key, value),
+            None => self.empty[0],
+        };
+
+        // the path with the bits below the current height cleared.
+        let mut prefix = path;
+        for height in 0..DEPTH {
+            self.set(height, prefix, hash);
+
+            let (byte, bit) = (31 - height / 8, 1 << (height % 8));
+            let mut sibling = prefix;
+            sibling[byte] ^= bit;
+            let sibling = self.get(height, &sibling);
+
+            hash = match prefix[byte] & bit != 0 {
+                true => node_hash(&sibling, &hash),
+                false => node_hash(&hash, &sibling),
+            };
+            prefix[byte] &= !bit;
+        }
+
+        self.set(DEPTH, [0; 32], hash);
+    }
+
+    pub fn root(&self) -> Hash {
+        hex_str(&self.get(DEPTH, &[0; 32]))
+    }
+
+    fn get(&self, height: usize, prefix: &[u8; 32]) -> [u8; 32] {
+        self.nodes
+            .get(&(height, *prefix))
+            .copied()
+            .unwrap_or(self.empty[height])
+    }
+
+    fn set(&mut self, height: usize, prefix: [u8; 32], hash: [u8; 32]) {
+        if hash == self.empty[height] {
+            self.nodes.remove(&(height, prefix));
+        } else {
+            self.nodes.insert((height, prefix), hash);
+        }
+    }
+}
+
+impl Default for StateTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn leaf_hash(key: &[u8], value: &[u8]) -> [u8; 32] {
+    let mut bytes = vec![0];
+    put_bytes(&mut bytes, key);
+    put_bytes(&mut bytes, value);
+
+    sha256(&bytes)
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut bytes = vec![1];
+    bytes.extend(left);
+    bytes.extend(right);
+
+    sha256(&bytes)
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    let mut result = [0; 32];
+    let mut hasher = Sha256::new();
+    hasher.input(bytes);
+    hasher.result(&mut result);
+
+    result
+}
+
+#[test]
+fn test_state_tree_order_independent() {
+    let mut tree = StateTree::new();
+    let empty = tree.root();
+
+    tree.update(b"a", Some(b"1"));
+    tree.update(b"b", Some(b"2"));
+    let root = tree.root();
+
+    let mut other = StateTree::new();
+    other.update(b"b", Some(b"2"));
+    other.update(b"a", Some(b"0"));
+    assert_ne!(other.root(), root);
+    other.update(b"a", Some(b"1"));
+    assert_eq!(other.root(), root);
+
+    // clearing every leaf brings back the empty root, and nothing is left behind.
+    other.update(b"a", None);
+    other.update(b"b", None);
+    assert_eq!(other.root(), empty);
+    assert!(other.nodes.is_empty());
+}
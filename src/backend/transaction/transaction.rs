@@ -1,10 +1,101 @@
-use crate::backend::{types::Address, types::Hash, Hashable};
+use crypto::{digest::Digest, ed25519};
+
+use crate::backend::{
+    contract::vm::Program,
+    enums::{BlockValidationError, DecodeError, ScriptError},
+    functions::{address_of, hex_bytes, hex_str, verify_hex_signature},
+    traits::encodable::{put_bytes, put_opt_str, put_str, ByteReader},
+    types::Address,
+    types::{AssetId, Hash, NftId},
+    Encodable, Hashable,
+};
+
+use super::{
+    asset::NATIVE_ASSET,
+    multisig::MultisigPolicy,
+    script::{Script, ScriptContext},
+};
+
+// bound of the window a transaction may be mined in, checked against the containing block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeLock {
+    Height(u64),
+    // nanoseconds, like block timestamps.
+    Timestamp(u64),
+}
+
+impl TimeLock {
+    // whether a block at `height` with `timestamp` is at or past the bound.
+    fn reached(&self, height: u64, timestamp: u64) -> bool {
+        match self {
+            TimeLock::Height(bound) => height >= *bound,
+            TimeLock::Timestamp(bound) => timestamp >= *bound,
+        }
+    }
+
+    // whether a block at `height` with `timestamp` is beyond the bound.
+    fn passed(&self, height: u64, timestamp: u64) -> bool {
+        match self {
+            TimeLock::Height(bound) => height > *bound,
+            TimeLock::Timestamp(bound) => timestamp > *bound,
+        }
+    }
+}
+
+// what a transaction does with its value.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TransactionKind {
+    // everything goes to `to_address`.
+    #[default]
+    Transfer,
+    // split over many receivers. the value is the sum of the payouts.
+    Batch(Vec<(Address, u64)>),
+    // create a contract running the program. the value is its starting balance.
+    Deploy(Program),
+    // run the contract at `to_address` with these arguments, paying it the value.
+    Call(Vec<u64>),
+    // create a token. its id is `asset_id` of the transaction id, and the issuer
+    // receives the whole supply.
+    Issue {
+        symbol: String,
+        decimals: u8,
+        supply: u64,
+    },
+    // create a non-fungible token with this metadata hash, owned by `to_address`.
+    // its id is `nft_id` of the transaction id.
+    Mint(Hash),
+    // hand the token over to `to_address`.
+    TransferNft(NftId),
+    Burn(NftId),
+}
 
 #[derive(Debug, Clone)]
 pub struct Transaction {
     from_address: Address,
     to_address: Address,
     value: u64,
+    // what the value is paid in. fees are always paid in the native coin.
+    asset: AssetId,
+    kind: TransactionKind,
+    // how many transactions the sender signed before this one.
+    nonce: u64,
+    // paid to the miner on top of the value.
+    fee: u64,
+    // hex ed25519 public key and signature. unsigned transactions have neither.
+    public_key: Option<Hash>,
+    signature: Option<Hash>,
+    // spending from a multisig address takes its policy and the signatures of the keys in it,
+    // as (hex public key, hex signature).
+    multisig: Option<MultisigPolicy>,
+    cosignatures: Vec<(Hash, Hash)>,
+    // spending from a script address reveals the script and the items it runs on.
+    script: Option<Script>,
+    witness: Vec<Vec<u8>>,
+    // first and last block the transaction may be mined in, both inclusive.
+    valid_after: Option<TimeLock>,
+    valid_until: Option<TimeLock>,
+    // opaque payload, such as an invoice reference. costs extra fee per byte.
+    data: Vec<u8>,
 }
 
 impl Transaction {
@@ -13,9 +104,184 @@ impl Transaction {
             from_address,
             to_address,
             value,
+            asset: NATIVE_ASSET.to_string(),
+            kind: TransactionKind::Transfer,
+            nonce: 0,
+            fee: 0,
+            public_key: None,
+            signature: None,
+            multisig: None,
+            cosignatures: vec![],
+            script: None,
+            witness: vec![],
+            valid_after: None,
+            valid_until: None,
+            data: vec![],
+        }
+    }
+
+    // one sender paying many receivers at once. all payouts happen, or none do.
+    pub fn batch(from_address: Hash, payouts: Vec<(Hash, u64)>) -> Self {
+        let value = payouts
+            .iter()
+            .try_fold(0_u64, |sum, (_, value)| sum.checked_add(*value))
+            .unwrap_or(u64::MAX);
+
+        Transaction {
+            kind: TransactionKind::Batch(payouts),
+            ..Transaction::new(from_address, String::new(), value)
         }
     }
 
+    // deploy a contract. it lives at `contract_address` of the transaction id.
+    pub fn deploy(from_address: Hash, program: Program, value: u64) -> Self {
+        Transaction {
+            kind: TransactionKind::Deploy(program),
+            ..Transaction::new(from_address, String::new(), value)
+        }
+    }
+
+    pub fn issue(from_address: Hash, symbol: String, decimals: u8, supply: u64) -> Self {
+        Transaction {
+            kind: TransactionKind::Issue {
+                symbol,
+                decimals,
+                supply,
+            },
+            ..Transaction::new(from_address, String::new(), 0)
+        }
+    }
+
+    pub fn mint(from_address: Hash, owner: Hash, metadata: Hash) -> Self {
+        Transaction {
+            kind: TransactionKind::Mint(metadata),
+            ..Transaction::new(from_address, owner, 0)
+        }
+    }
+
+    pub fn transfer_nft(from_address: Hash, nft: NftId, to_address: Hash) -> Self {
+        Transaction {
+            kind: TransactionKind::TransferNft(nft),
+            ..Transaction::new(from_address, to_address, 0)
+        }
+    }
+
+    pub fn burn(from_address: Hash, nft: NftId) -> Self {
+        Transaction {
+            kind: TransactionKind::Burn(nft),
+            ..Transaction::new(from_address, String::new(), 0)
+        }
+    }
+
+    pub fn call(from_address: Hash, contract: Hash, value: u64, args: Vec<u64>) -> Self {
+        Transaction {
+            kind: TransactionKind::Call(args),
+            ..Transaction::new(from_address, contract, value)
+        }
+    }
+
+    // transaction from the address of `secret_key`, as returned by `ed25519::keypair`.
+    pub fn signed(
+        secret_key: &[u8; 64],
+        to_address: Hash,
+        value: u64,
+        nonce: u64,
+        fee: u64,
+    ) -> Self {
+        Transaction::new(address_of(&secret_key[32..]), to_address, value)
+            .with_nonce(nonce)
+            .with_fee(fee)
+            .sign(secret_key)
+    }
+
+    // replacement for a pending transaction, moving nothing but the fee.
+    pub fn cancel(secret_key: &[u8; 64], nonce: u64, fee: u64) -> Self {
+        Transaction::signed(secret_key, address_of(&secret_key[32..]), 0, nonce, fee)
+    }
+
+    // pay the value of a transfer or batch in another asset than the native coin.
+    pub fn with_asset(mut self, asset: AssetId) -> Self {
+        self.asset = asset;
+        self
+    }
+
+    pub fn with_nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    // the window is signed, so set it before signing.
+    pub fn with_validity(
+        mut self,
+        valid_after: Option<TimeLock>,
+        valid_until: Option<TimeLock>,
+    ) -> Self {
+        self.valid_after = valid_after;
+        self.valid_until = valid_until;
+        self
+    }
+
+    // sign everything but the signature itself. the public half sits at the end of the secret key.
+    pub fn sign(mut self, secret_key: &[u8; 64]) -> Self {
+        let signature = ed25519::signature(&self.bytes(), secret_key);
+
+        self.public_key = Some(hex_str(&secret_key[32..]));
+        self.signature = Some(hex_str(&signature));
+        self
+    }
+
+    // spend from the address of `policy`. every cosigner signs afterwards.
+    pub fn with_multisig(mut self, policy: MultisigPolicy) -> Self {
+        self.multisig = Some(policy);
+        self
+    }
+
+    // add the signature of one key of the multisig policy.
+    pub fn cosign(mut self, secret_key: &[u8; 64]) -> Self {
+        let signature = ed25519::signature(&self.bytes(), secret_key);
+
+        self.cosignatures
+            .push((hex_str(&secret_key[32..]), hex_str(&signature)));
+        self
+    }
+
+    pub fn multisig(&self) -> Option<&MultisigPolicy> {
+        self.multisig.as_ref()
+    }
+
+    pub fn cosignatures(&self) -> &Vec<(Hash, Hash)> {
+        &self.cosignatures
+    }
+
+    // spend from the address of `script`.
+    pub fn with_script(mut self, script: Script) -> Self {
+        self.script = Some(script);
+        self
+    }
+
+    pub fn with_witness(mut self, witness: Vec<Vec<u8>>) -> Self {
+        self.witness = witness;
+        self
+    }
+
+    // signature of the transaction for a witness, as checked by `Op::CheckSig`.
+    pub fn script_signature(&self, secret_key: &[u8; 64]) -> Vec<u8> {
+        ed25519::signature(&self.bytes(), secret_key).to_vec()
+    }
+
+    pub fn script(&self) -> Option<&Script> {
+        self.script.as_ref()
+    }
+
+    pub fn witness(&self) -> &Vec<Vec<u8>> {
+        &self.witness
+    }
+
     pub fn value(&self) -> &u64 {
         &self.value
     }
@@ -27,6 +293,187 @@ impl Transaction {
     pub fn to_address(&self) -> &Address{
         &self.to_address
     }
+
+    pub fn asset(&self) -> &AssetId {
+        &self.asset
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.asset == NATIVE_ASSET
+    }
+
+    pub fn kind(&self) -> &TransactionKind {
+        &self.kind
+    }
+
+    // every receiver with what it gets.
+    pub fn payouts(&self) -> Vec<(&Address, u64)> {
+        match &self.kind {
+            TransactionKind::Transfer => vec![(&self.to_address, self.value)],
+            TransactionKind::Batch(payouts) => payouts
+                .iter()
+                .map(|(address, value)| (address, *value))
+                .collect(),
+            // the contract machine moves the value.
+            TransactionKind::Deploy(_) | TransactionKind::Call(_) => vec![],
+            TransactionKind::Issue { .. }
+            | TransactionKind::Mint(_)
+            | TransactionKind::TransferNft(_)
+            | TransactionKind::Burn(_) => vec![],
+        }
+    }
+
+    // deploys or calls a contract, so it only takes effect through the contract machine.
+    pub fn is_contract(&self) -> bool {
+        matches!(
+            self.kind,
+            TransactionKind::Deploy(_) | TransactionKind::Call(_)
+        )
+    }
+
+    // a batch has payouts, and they add up to its value.
+    pub fn check_payouts(&self) -> Result<(), BlockValidationError> {
+        if let TransactionKind::Batch(payouts) = &self.kind {
+            let sum = payouts
+                .iter()
+                .try_fold(0_u64, |sum, (_, value)| sum.checked_add(*value));
+
+            if payouts.is_empty() || sum != Some(self.value) {
+                return Err(BlockValidationError::InvalidBatch);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn fee(&self) -> u64 {
+        self.fee
+    }
+
+    pub fn public_key(&self) -> Option<&Hash> {
+        self.public_key.as_ref()
+    }
+
+    pub fn signature(&self) -> Option<&Hash> {
+        self.signature.as_ref()
+    }
+
+    // the data is signed, so set it before signing.
+    pub fn with_data(mut self, data: Vec<u8>) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn valid_after(&self) -> Option<TimeLock> {
+        self.valid_after
+    }
+
+    pub fn valid_until(&self) -> Option<TimeLock> {
+        self.valid_until
+    }
+
+    // whether a block at `height` with `timestamp` may contain the transaction.
+    pub fn check_validity(&self, height: u64, timestamp: u64) -> Result<(), BlockValidationError> {
+        if let Some(lock) = self.valid_after {
+            if !lock.reached(height, timestamp) {
+                return Err(BlockValidationError::TransactionNotYetValid);
+            }
+        }
+
+        if self.is_expired(height, timestamp) {
+            return Err(BlockValidationError::TransactionExpired);
+        }
+
+        Ok(())
+    }
+
+    // no block at `height` or later than `timestamp` may contain the transaction anymore.
+    pub fn is_expired(&self, height: u64, timestamp: u64) -> bool {
+        self.valid_until
+            .is_some_and(|lock| lock.passed(height, timestamp))
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.public_key.is_some()
+            || self.signature.is_some()
+            || self.multisig.is_some()
+            || !self.cosignatures.is_empty()
+            || self.script.is_some()
+    }
+
+    // the signature is valid and made with the key the sender address belongs to,
+    // or enough keys of the multisig policy behind the sender address signed.
+    // a script only has to match the sender address here. it runs in `check_script`,
+    // as it may depend on the block.
+    pub fn check_signatures(&self) -> Result<(), BlockValidationError> {
+        let message = self.bytes();
+
+        if let Some(script) = &self.script {
+            return match self.multisig.is_none()
+                && self.public_key.is_none()
+                && self.signature.is_none()
+                && self.cosignatures.is_empty()
+                && script.address() == self.from_address
+            {
+                true => Ok(()),
+                false => Err(BlockValidationError::InvalidSignature),
+            };
+        }
+
+        match (&self.multisig, &self.public_key, &self.signature) {
+            (Some(policy), None, None) => {
+                policy.check(&self.from_address, &message, &self.cosignatures)
+            }
+            (None, Some(public_key), Some(signature))
+                if self.cosignatures.is_empty()
+                    && hex_bytes(public_key)
+                        .is_some_and(|bytes| address_of(&bytes) == self.from_address)
+                    && verify_hex_signature(&message, public_key, signature) =>
+            {
+                Ok(())
+            }
+            _ => Err(BlockValidationError::InvalidSignature),
+        }
+    }
+
+    // run the script of the transaction, if it has one, for a block at `height` with `timestamp`.
+    pub fn check_script(
+        &self,
+        height: u64,
+        timestamp: u64,
+        max_steps: usize,
+    ) -> Result<(), ScriptError> {
+        match &self.script {
+            Some(script) => {
+                let message = self.bytes();
+                let context = ScriptContext::new(&message, height, timestamp);
+                script.eval(&self.witness, &context, max_steps)
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn verify_signature(&self) -> bool {
+        self.check_signatures().is_ok()
+    }
+
+    // hex hash identifying the transaction. the signature is left out.
+    pub fn id(&self) -> Hash {
+        self.hash().result_str()
+    }
+
+    // serialized size in bytes.
+    pub fn size(&self) -> usize {
+        self.encode().len()
+    }
 }
 
 impl Hashable for Transaction {
@@ -36,7 +483,273 @@ impl Hashable for Transaction {
         result.extend(self.from_address.as_bytes());
         result.extend(self.to_address.as_bytes());
         result.extend(self.value.to_le_bytes());
+        put_str(&mut result, &self.asset);
+        put_kind(&mut result, &self.kind);
+        result.extend(self.nonce.to_le_bytes());
+        result.extend(self.fee.to_le_bytes());
+        put_lock(&mut result, &self.valid_after);
+        put_lock(&mut result, &self.valid_until);
+        put_bytes(&mut result, &self.data);
+
+        result
+    }
+}
+
+impl Encodable for Transaction {
+    fn encode(&self) -> Vec<u8> {
+        let mut result = vec![];
+
+        put_str(&mut result, &self.from_address);
+        put_str(&mut result, &self.to_address);
+        result.extend(self.value.to_le_bytes());
+        put_str(&mut result, &self.asset);
+        put_kind(&mut result, &self.kind);
+        result.extend(self.nonce.to_le_bytes());
+        result.extend(self.fee.to_le_bytes());
+        put_lock(&mut result, &self.valid_after);
+        put_lock(&mut result, &self.valid_until);
+        put_bytes(&mut result, &self.data);
+        put_opt_str(&mut result, &self.public_key);
+        put_opt_str(&mut result, &self.signature);
+        match &self.multisig {
+            Some(policy) => {
+                result.push(1);
+                result.extend(policy.encode());
+            }
+            None => result.push(0),
+        }
+        result.extend((self.cosignatures.len() as u32).to_le_bytes());
+        for (public_key, signature) in self.cosignatures.iter() {
+            put_str(&mut result, public_key);
+            put_str(&mut result, signature);
+        }
+        match &self.script {
+            Some(script) => {
+                result.push(1);
+                result.extend(script.encode());
+            }
+            None => result.push(0),
+        }
+        result.extend((self.witness.len() as u32).to_le_bytes());
+        for item in self.witness.iter() {
+            put_bytes(&mut result, item);
+        }
 
         result
     }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        let from_address = reader.string()?;
+        let to_address = reader.string()?;
+        let value = reader.u64()?;
+
+        Ok(Transaction {
+            from_address,
+            to_address,
+            value,
+            asset: reader.string()?,
+            kind: read_kind(reader)?,
+            nonce: reader.u64()?,
+            fee: reader.u64()?,
+            valid_after: read_lock(reader)?,
+            valid_until: read_lock(reader)?,
+            data: reader.bytes()?,
+            public_key: reader.opt_string()?,
+            signature: reader.opt_string()?,
+            multisig: match reader.u8()? {
+                0 => None,
+                1 => Some(MultisigPolicy::decode(reader)?),
+                _ => return Err(DecodeError::InvalidData),
+            },
+            cosignatures: {
+                let count = reader.u32()?;
+                let mut cosignatures = vec![];
+                for _ in 0..count {
+                    cosignatures.push((reader.string()?, reader.string()?));
+                }
+                cosignatures
+            },
+            script: match reader.u8()? {
+                0 => None,
+                1 => Some(Script::decode(reader)?),
+                _ => return Err(DecodeError::InvalidData),
+            },
+            witness: {
+                let count = reader.u32()?;
+                let mut witness = vec![];
+                for _ in 0..count {
+                    witness.push(reader.bytes()?);
+                }
+                witness
+            },
+        })
+    }
+}
+
+fn put_kind(out: &mut Vec<u8>, kind: &TransactionKind) {
+    match kind {
+        TransactionKind::Transfer => out.push(0),
+        TransactionKind::Batch(payouts) => {
+            out.push(1);
+            out.extend((payouts.len() as u32).to_le_bytes());
+            for (address, value) in payouts {
+                put_str(out, address);
+                out.extend(value.to_le_bytes());
+            }
+        }
+        TransactionKind::Deploy(program) => {
+            out.push(2);
+            out.extend(program.encode());
+        }
+        TransactionKind::Call(args) => {
+            out.push(3);
+            out.extend((args.len() as u32).to_le_bytes());
+            for arg in args {
+                out.extend(arg.to_le_bytes());
+            }
+        }
+        TransactionKind::Issue {
+            symbol,
+            decimals,
+            supply,
+        } => {
+            out.push(4);
+            put_str(out, symbol);
+            out.push(*decimals);
+            out.extend(supply.to_le_bytes());
+        }
+        TransactionKind::Mint(metadata) => {
+            out.push(5);
+            put_str(out, metadata);
+        }
+        TransactionKind::TransferNft(nft) => {
+            out.push(6);
+            put_str(out, nft);
+        }
+        TransactionKind::Burn(nft) => {
+            out.push(7);
+            put_str(out, nft);
+        }
+    }
+}
+
+fn read_kind(reader: &mut ByteReader) -> Result<TransactionKind, DecodeError> {
+    match reader.u8()? {
+        0 => Ok(TransactionKind::Transfer),
+        1 => {
+            let count = reader.u32()?;
+            let mut payouts = vec![];
+            for _ in 0..count {
+                payouts.push((reader.string()?, reader.u64()?));
+            }
+
+            Ok(TransactionKind::Batch(payouts))
+        }
+        2 => Ok(TransactionKind::Deploy(Program::decode(reader)?)),
+        3 => {
+            let count = reader.u32()?;
+            let mut args = vec![];
+            for _ in 0..count {
+                args.push(reader.u64()?);
+            }
+
+            Ok(TransactionKind::Call(args))
+        }
+        4 => Ok(TransactionKind::Issue {
+            symbol: reader.string()?,
+            decimals: reader.u8()?,
+            supply: reader.u64()?,
+        }),
+        5 => Ok(TransactionKind::Mint(reader.string()?)),
+        6 => Ok(TransactionKind::TransferNft(reader.string()?)),
+        7 => Ok(TransactionKind::Burn(reader.string()?)),
+        _ => Err(DecodeError::InvalidData),
+    }
+}
+
+fn put_lock(out: &mut Vec<u8>, lock: &Option<TimeLock>) {
+    match lock {
+        None => out.push(0),
+        Some(TimeLock::Height(height)) => {
+            out.push(1);
+            out.extend(height.to_le_bytes());
+        }
+        Some(TimeLock::Timestamp(timestamp)) => {
+            out.push(2);
+            out.extend(timestamp.to_le_bytes());
+        }
+    }
+}
+
+fn read_lock(reader: &mut ByteReader) -> Result<Option<TimeLock>, DecodeError> {
+    match reader.u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(TimeLock::Height(reader.u64()?))),
+        2 => Ok(Some(TimeLock::Timestamp(reader.u64()?))),
+        _ => Err(DecodeError::InvalidData),
+    }
+}
+
+#[test]
+fn test_signed_transaction() {
+    let (secret_key, public_key) = ed25519::keypair(&[1; 32]);
+    let tx = Transaction::signed(&secret_key, "bob".to_string(), 5, 0, 1);
+
+    assert_eq!(tx.from_address(), &address_of(&public_key));
+    assert!(tx.verify_signature());
+
+    let decoded = Transaction::from_bytes(&tx.encode()).unwrap();
+    assert_eq!(decoded.id(), tx.id());
+    assert!(decoded.verify_signature());
+
+    // changing anything signed breaks the signature.
+    let mut altered = tx.clone();
+    altered.fee = 0;
+    assert!(!altered.verify_signature());
+
+    // a valid signature by someone else than the sender.
+    let other = ed25519::keypair(&[2; 32]).0;
+    let forged = Transaction::new(tx.from_address().clone(), "bob".to_string(), 5).sign(&other);
+    assert!(!forged.verify_signature());
+}
+
+#[test]
+fn test_transaction_data() {
+    use crate::backend::{ChainParams, Mempool, MempoolError, ValueStore};
+
+    let (secret_key, _) = ed25519::keypair(&[1; 32]);
+    let plain = Transaction::signed(&secret_key, "shop".to_string(), 5, 0, 1);
+    let invoice = Transaction::new(plain.from_address().clone(), "shop".to_string(), 5)
+        .with_fee(9)
+        .with_data(b"inv-0042".to_vec())
+        .sign(&secret_key);
+
+    // the data is part of what is hashed, signed and stored.
+    assert_ne!(invoice.id(), plain.clone().with_fee(9).id());
+    assert!(invoice.verify_signature());
+    assert_eq!(
+        Transaction::from_bytes(&invoice.encode()).unwrap().data(),
+        b"inv-0042"
+    );
+
+    let mut pool = Mempool::new(10, &ChainParams::regtest().with_data_limits(8, 2));
+    let state = ValueStore::new();
+    assert!(matches!(
+        pool.add(invoice, &state),
+        Err(MempoolError::InsufficientDataFee {
+            fee: 9,
+            required: 16
+        })
+    ));
+
+    let too_long = Transaction::signed(&secret_key, "shop".to_string(), 5, 0, 100)
+        .with_data(vec![0; 9])
+        .sign(&secret_key);
+    assert!(matches!(
+        pool.add(too_long, &state),
+        Err(MempoolError::DataTooLarge {
+            size: 9,
+            max_tx_data: 8
+        })
+    ));
 }
@@ -7,7 +7,10 @@ use std::collections::HashSet;
 
 use crypto::{digest::Digest, sha2::Sha256};
 
-use crate::backend::{types::Hash, Hashable, Transaction};
+use crate::backend::{
+    enums::DecodeError, traits::encodable::ByteReader, types::Hash, Encodable, Hashable,
+    Transaction,
+};
 
 #[derive(Debug, Clone)]
 pub struct Transactions {
@@ -51,8 +54,19 @@ impl Transactions {
     //         .collect::<HashSet<Hash>>()
     // }
 
+    // single hash committing to every transaction, in order.
+    pub fn root(&self) -> Hash {
+        let mut hasher = Sha256::new();
+
+        for tx in self.values.iter() {
+            hasher.input(&Self::get_vec(&mut tx.hash()));
+        }
+
+        hasher.result_str()
+    }
+
     pub fn is_input_empty(&self) -> bool {
-        self.values.len() == 0
+        self.values.is_empty()
     }
 
     fn hash_str(mut sha256: Sha256) -> Hash {
@@ -82,3 +96,27 @@ impl Hashable for Transactions {
         bytes
     }
 }
+
+impl Encodable for Transactions {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        bytes.extend((self.values.len() as u32).to_le_bytes());
+        for tx in self.values.iter() {
+            bytes.extend(tx.encode());
+        }
+
+        bytes
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        let count = reader.u32()?;
+        let mut transactions = Transactions::empty();
+
+        for _ in 0..count {
+            transactions.values.push(Transaction::decode(reader)?);
+        }
+
+        Ok(transactions)
+    }
+}
@@ -1,49 +1,362 @@
-use crate::backend::{enums::BlockValidationError, types::Hash, Block};
-use std::collections::HashMap;
+use crate::backend::{
+    contract::{
+        receipt::Receipt,
+        vm::{self, contract_address, CallContext, ContractState, Program},
+    },
+    enums::{BlockValidationError, DecodeError},
+    params::ChainParams,
+    traits::encodable::{put_bytes, put_str, ByteReader},
+    types::{AssetId, Hash, NftId, StateEntry, StateWrite},
+    Block, Encodable, Transaction, TransactionKind,
+};
 
-#[derive(Debug)]
+use super::{
+    asset::{asset_id, Token, NATIVE_ASSET},
+    nft::{nft_id, Nft},
+    statetree::StateTree,
+};
+use std::collections::{HashMap, HashSet};
+
+// state keys are namespaced so other kinds of state can share the store later.
+const BALANCE_PREFIX: &[u8] = b"balance/";
+const NONCE_PREFIX: &[u8] = b"nonce/";
+// followed by the asset id, a '/' and the address. native balances keep the balance prefix.
+const ASSET_PREFIX: &[u8] = b"asset/";
+const TOKEN_PREFIX: &[u8] = b"token/";
+const NFT_PREFIX: &[u8] = b"nft/";
+const CODE_PREFIX: &[u8] = b"code/";
+// followed by the contract address, a '/' and the slot.
+const STORAGE_PREFIX: &[u8] = b"storage/";
+
+// what a raw state key refers to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum StateKey {
+    Balance(Hash, AssetId),
+    Nonce(Hash),
+    Token(AssetId),
+    Nft(NftId),
+    Code(Hash),
+    Storage(Hash, u64),
+}
+
+impl StateKey {
+    fn to_bytes(&self) -> Vec<u8> {
+        let (prefix, address) = match self {
+            StateKey::Balance(address, asset) if asset == NATIVE_ASSET => (BALANCE_PREFIX, address),
+            StateKey::Balance(address, _) => (ASSET_PREFIX, address),
+            StateKey::Nonce(address) => (NONCE_PREFIX, address),
+            StateKey::Token(asset) => (TOKEN_PREFIX, asset),
+            StateKey::Nft(nft) => (NFT_PREFIX, nft),
+            StateKey::Code(address) => (CODE_PREFIX, address),
+            StateKey::Storage(address, _) => (STORAGE_PREFIX, address),
+        };
+
+        let mut key = prefix.to_vec();
+        match self {
+            StateKey::Balance(_, asset) if asset != NATIVE_ASSET => {
+                key.extend(asset.as_bytes());
+                key.push(b'/');
+                key.extend(address.as_bytes());
+            }
+            StateKey::Storage(_, slot) => {
+                key.extend(address.as_bytes());
+                key.push(b'/');
+                key.extend(slot.to_be_bytes());
+            }
+            _ => key.extend(address.as_bytes()),
+        }
+        key
+    }
+}
+
+// what a state key holds. tokens and contract code are kept decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StateValue {
+    Word(u64),
+    Token(Token),
+    Nft(Nft),
+    Code(Program),
+}
+
+impl StateValue {
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            StateValue::Word(word) => word.to_le_bytes().to_vec(),
+            StateValue::Token(token) => token.encode(),
+            StateValue::Nft(nft) => nft.encode(),
+            StateValue::Code(program) => program.encode(),
+        }
+    }
+
+    fn word(self) -> Option<u64> {
+        match self {
+            StateValue::Word(word) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn token(self) -> Option<Token> {
+        match self {
+            StateValue::Token(token) => Some(token),
+            _ => None,
+        }
+    }
+
+    fn nft(self) -> Option<Nft> {
+        match self {
+            StateValue::Nft(nft) => Some(nft),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> Option<Program> {
+        match self {
+            StateValue::Code(program) => Some(program),
+            _ => None,
+        }
+    }
+}
+
+// the block a transaction is applied in. scripts may depend on its height and time.
+#[derive(Debug, Clone, Copy)]
+pub struct SpendContext {
+    height: u64,
+    timestamp: u64,
+    max_script_steps: usize,
+}
+
+impl SpendContext {
+    pub fn new(height: u64, timestamp: u64, params: &ChainParams) -> Self {
+        SpendContext {
+            height,
+            timestamp,
+            max_script_steps: params.max_script_steps(),
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct ValueStore {
-    values_store: HashMap<Hash, u64>,
+    // balances by address and asset.
+    values_store: HashMap<(Hash, AssetId), u64>,
+    // signed transactions each address made so far.
+    nonces: HashMap<Hash, u64>,
+    tokens: HashMap<AssetId, Token>,
+    nfts: HashMap<NftId, Nft>,
+    // contract code and the words contracts stored, by contract address and slot.
+    // empty slots aren't kept.
+    code: HashMap<Hash, Program>,
+    storage: HashMap<(Hash, u64), u64>,
+    // previous values of everything changed since the last commit / rollback.
+    journal: Vec<(StateKey, Option<StateValue>)>,
+    tree: StateTree,
 }
 
 impl ValueStore {
     pub fn new() -> Self {
         ValueStore {
-            values_store: HashMap::<Hash, u64>::new(),
+            values_store: HashMap::<(Hash, AssetId), u64>::new(),
+            nonces: HashMap::new(),
+            tokens: HashMap::new(),
+            nfts: HashMap::new(),
+            code: HashMap::new(),
+            storage: HashMap::new(),
+            journal: vec![],
+            tree: StateTree::new(),
         }
     }
 
-    pub fn reward_miner(&mut self, block_chain: &Vec<Block>, rewarder: Hash) {
-        if let None = block_chain.last() {
-            return;
+    // rebuild from entries previously read out of a `ChainStore`.
+    pub fn from_state(entries: Vec<StateEntry>) -> Result<Self, DecodeError> {
+        let mut store = ValueStore::new();
+
+        for (key, value) in entries {
+            let (key, value) = parse_entry(&key, &value)?;
+            store.put(&key, Some(value));
         }
 
-        let last_block = block_chain.last().unwrap();
+        Ok(store)
+    }
 
-        if *last_block.broadcaster() == rewarder {
-            if let Some(value) = self.values_store.get_mut(&rewarder) {
-                *value = *value + 1;
-            } else {
-                self.values_store.insert(rewarder, 1);
+    // put back raw writes, such as the undo data of a block. they are journaled like any change.
+    pub fn apply_writes(&mut self, writes: &[StateWrite]) -> Result<(), DecodeError> {
+        for (key, value) in writes {
+            match value {
+                Some(value) => {
+                    let (key, value) = parse_entry(key, value)?;
+                    self.write(key, Some(value));
+                }
+                None => {
+                    let key = parse_key(key)?;
+                    self.write(key, None);
+                }
             }
         }
+
+        Ok(())
+    }
+
+    pub fn journal_len(&self) -> usize {
+        self.journal.len()
+    }
+
+    // writes restoring the values from before the journal was `mark` entries long.
+    pub fn undo_since(&self, mark: usize) -> Vec<StateWrite> {
+        let mut seen = HashSet::new();
+
+        self.journal[mark..]
+            .iter()
+            .filter(|(key, _)| seen.insert(key.clone()))
+            .map(|(key, previous)| (key.to_bytes(), previous.as_ref().map(StateValue::to_bytes)))
+            .collect()
+    }
+
+    // every balance, nonce and contract as raw entries, ordered by key.
+    pub fn state_entries(&self) -> Vec<StateEntry> {
+        let balances = self.values_store.iter().map(|((address, asset), value)| {
+            (StateKey::Balance(address.clone(), asset.clone()), *value)
+        });
+        let nonces = self
+            .nonces
+            .iter()
+            .map(|(address, nonce)| (StateKey::Nonce(address.clone()), *nonce));
+        let storage = self
+            .storage
+            .iter()
+            .map(|((address, slot), word)| (StateKey::Storage(address.clone(), *slot), *word));
+
+        let words = balances
+            .chain(nonces)
+            .chain(storage)
+            .map(|(key, value)| (key.to_bytes(), value.to_le_bytes().to_vec()));
+        let tokens = self
+            .tokens
+            .iter()
+            .map(|(asset, token)| (StateKey::Token(asset.clone()).to_bytes(), token.encode()));
+        let nfts = self
+            .nfts
+            .iter()
+            .map(|(id, nft)| (StateKey::Nft(id.clone()).to_bytes(), nft.encode()));
+        let code = self.code.iter().map(|(address, program)| {
+            (StateKey::Code(address.clone()).to_bytes(), program.encode())
+        });
+
+        let mut entries = words
+            .chain(tokens)
+            .chain(nfts)
+            .chain(code)
+            .collect::<Vec<StateEntry>>();
+        entries.sort();
+
+        entries
+    }
+
+    // root of the state tree over every entry, committed to by block hashes.
+    // kept up to date with every change, so it costs nothing to read.
+    pub fn commitment(&self) -> Hash {
+        self.tree.root()
+    }
+
+    // copy of the committed state after the block `height` with the given hash.
+    pub fn export_snapshot(&self, height: u64, block_hash: Hash) -> StateSnapshot {
+        StateSnapshot {
+            height,
+            block_hash,
+            entries: self.state_entries(),
+        }
+    }
+
+    pub fn import_snapshot(snapshot: &StateSnapshot) -> Result<Self, DecodeError> {
+        Self::from_state(snapshot.entries.clone())
+    }
+
+    // balance in the native coin.
+    pub fn balance(&self, address: &Hash) -> Option<u64> {
+        self.asset_balance(address, NATIVE_ASSET)
+    }
+
+    pub fn asset_balance(&self, address: &Hash, asset: &str) -> Option<u64> {
+        self.values_store
+            .get(&(address.clone(), asset.to_string()))
+            .copied()
+    }
+
+    pub fn token(&self, asset: &AssetId) -> Option<&Token> {
+        self.tokens.get(asset)
+    }
+
+    pub fn nft(&self, nft: &NftId) -> Option<&Nft> {
+        self.nfts.get(nft)
+    }
+
+    // ids of the non-fungible tokens `owner` holds, sorted.
+    pub fn nfts_of(&self, owner: &Hash) -> Vec<&NftId> {
+        let mut owned = self
+            .nfts
+            .iter()
+            .filter(|(_, nft)| nft.owner() == owner)
+            .map(|(id, _)| id)
+            .collect::<Vec<&NftId>>();
+        owned.sort();
+        owned
+    }
+
+    // nonce the next signed transaction of `address` has to carry.
+    pub fn nonce(&self, address: &Hash) -> u64 {
+        self.nonces.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn contract_code(&self, contract: &Hash) -> Option<&Program> {
+        self.code.get(contract)
     }
 
-    pub fn insert_genesis_balance(
+    // word a contract stored at `slot`, 0 if none is.
+    pub fn storage(&self, contract: &Hash, slot: u64) -> u64 {
+        self.storage
+            .get(&(contract.clone(), slot))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn reward_miner(
         &mut self,
-        block: &mut Block,
+        last_block: Option<&Block>,
+        rewarder: Hash,
+        reward: u64,
     ) -> Result<(), BlockValidationError> {
+        let last_block = match last_block {
+            Some(block) => block,
+            None => return Ok(()),
+        };
+
+        if *last_block.broadcaster() == rewarder {
+            let current = self.balance(&rewarder).unwrap_or(0);
+            let rewarded = current
+                .checked_add(reward)
+                .ok_or(BlockValidationError::InvalidInput)?;
+            self.write_balance(&rewarder, Some(rewarded));
+        }
+
+        Ok(())
+    }
+
+    pub fn insert_genesis_balance(&mut self, block: &Block) -> Result<(), BlockValidationError> {
         let transactions = block.transactions();
 
-        if transactions.values().len() == 0 || block.index() != 0 {
+        if transactions.values().is_empty() || block.index() != 0 {
             return Err(BlockValidationError::InvalidInput);
         }
 
-        let genesis_tx = transactions.values().first().unwrap();
-        self.values_store
-            .insert(genesis_tx.to_address().to_string(), *genesis_tx.value());
+        // every output of the genesis block is an initial allocation.
+        for genesis_tx in transactions.values() {
+            self.set_value(genesis_tx.to_address(), *genesis_tx.value(), false)?;
+        }
 
-        return Ok(());
+        Ok(())
     }
 
     pub fn set_transaction(
@@ -58,7 +371,374 @@ impl ValueStore {
             }
         }
         // in case of not retruning anything yet.
-        return Err(BlockValidationError::InvalidInput);
+        Err(BlockValidationError::InvalidInput)
+    }
+
+    // move the value to the receivers and the fee to `miner`.
+    // the sender has to sign, or satisfy its script in `context`, and carry its next nonce.
+    // a transaction which fails leaves no change behind.
+    pub fn apply_transaction(
+        &mut self,
+        tx: &Transaction,
+        miner: &Hash,
+        context: &SpendContext,
+    ) -> Result<(), BlockValidationError> {
+        self.apply_checked(tx, miner, context, true)
+    }
+
+    // like `apply_transaction`, but the signatures and scripts are only checked if
+    // `check_signatures` is set. the chain trusts them below the assumed valid block.
+    pub(crate) fn apply_checked(
+        &mut self,
+        tx: &Transaction,
+        miner: &Hash,
+        context: &SpendContext,
+        check_signatures: bool,
+    ) -> Result<(), BlockValidationError> {
+        let mark = self.journal_len();
+
+        let result = self.transfer(tx, miner, context, check_signatures);
+        if result.is_err() {
+            self.rollback_to(mark);
+        }
+
+        result
+    }
+
+    // deploy or call a contract. the sender pays the whole fee up front, and gets back what
+    // the gas used and the data of the transaction leave of it. a failing contract keeps
+    // none of its changes, but the transaction still counts and pays for its gas.
+    pub fn apply_contract_transaction(
+        &mut self,
+        tx: &Transaction,
+        miner: &Hash,
+        params: &ChainParams,
+        context: &SpendContext,
+    ) -> Result<Receipt, BlockValidationError> {
+        self.apply_contract_checked(tx, miner, params, context, true)
+    }
+
+    // like `apply_contract_transaction`, with the signatures checked as in `apply_checked`.
+    pub(crate) fn apply_contract_checked(
+        &mut self,
+        tx: &Transaction,
+        miner: &Hash,
+        params: &ChainParams,
+        context: &SpendContext,
+        check_signatures: bool,
+    ) -> Result<Receipt, BlockValidationError> {
+        let mark = self.journal_len();
+
+        let result = self.run_contract(tx, miner, params, context, check_signatures);
+        if result.is_err() {
+            self.rollback_to(mark);
+        }
+
+        result
+    }
+
+    // drop the journal and return the writes needed to persist the changes.
+    pub fn commit(&mut self) -> Vec<StateWrite> {
+        let mut seen = HashSet::new();
+        let mut writes = vec![];
+
+        for (key, _) in std::mem::take(&mut self.journal) {
+            if seen.insert(key.clone()) {
+                let value = self.get(&key).as_ref().map(StateValue::to_bytes);
+                writes.push((key.to_bytes(), value));
+            }
+        }
+
+        writes
+    }
+
+    // undo every change since the last commit.
+    pub fn rollback(&mut self) {
+        self.rollback_to(0);
+    }
+
+    // undo the changes since the journal was `mark` entries long.
+    fn rollback_to(&mut self, mark: usize) {
+        while self.journal.len() > mark {
+            if let Some((key, previous)) = self.journal.pop() {
+                self.put(&key, previous);
+            }
+        }
+    }
+
+    fn transfer(
+        &mut self,
+        tx: &Transaction,
+        miner: &Hash,
+        context: &SpendContext,
+        check_signatures: bool,
+    ) -> Result<(), BlockValidationError> {
+        let from_address = tx.from_address();
+        tx.check_payouts()?;
+
+        // contracts pay for gas instead, so they can't go through here.
+        if tx.is_contract() {
+            return Err(BlockValidationError::InvalidInput);
+        }
+
+        self.authorize(tx, context, check_signatures)?;
+
+        match tx.kind() {
+            TransactionKind::Issue { .. } => return self.issue(tx, miner),
+            TransactionKind::Mint(_)
+            | TransactionKind::TransferNft(_)
+            | TransactionKind::Burn(_) => return self.apply_nft(tx, miner),
+            _ => {}
+        }
+
+        if tx.is_native() {
+            let total = tx
+                .value()
+                .checked_add(tx.fee())
+                .ok_or(BlockValidationError::InsufficientInputVal)?;
+
+            self.set_value(from_address, total, true)?;
+        } else {
+            if !self.tokens.contains_key(tx.asset()) {
+                return Err(BlockValidationError::InvalidToken);
+            }
+
+            self.set_value(from_address, tx.fee(), true)?;
+            self.set_asset_value(from_address, tx.asset(), *tx.value(), true)?;
+        }
+
+        for (to_address, value) in tx.payouts() {
+            self.set_asset_value(to_address, tx.asset(), value, false)?;
+        }
+        self.set_value(miner, tx.fee(), false)
+    }
+
+    // mint, hand over or burn a non-fungible token. these move no value, only the fee.
+    fn apply_nft(&mut self, tx: &Transaction, miner: &Hash) -> Result<(), BlockValidationError> {
+        let from_address = tx.from_address();
+        if *tx.value() != 0 || !tx.is_native() {
+            return Err(BlockValidationError::InvalidInput);
+        }
+
+        let (id, nft) = match tx.kind() {
+            TransactionKind::Mint(metadata) => {
+                // the nonce keeps a mint from being applied twice, the registry checks anyway.
+                let id = nft_id(&tx.id());
+                if self.nfts.contains_key(&id) {
+                    return Err(BlockValidationError::NftExists);
+                }
+
+                let nft = Nft::new(
+                    tx.to_address().clone(),
+                    metadata.clone(),
+                    from_address.clone(),
+                );
+                (id, Some(nft))
+            }
+            TransactionKind::TransferNft(id) | TransactionKind::Burn(id) => {
+                let nft = self.nfts.get(id).ok_or(BlockValidationError::UnknownNft)?;
+                if nft.owner() != from_address {
+                    return Err(BlockValidationError::NotNftOwner);
+                }
+
+                let nft = match tx.kind() {
+                    TransactionKind::TransferNft(_) => {
+                        Some(nft.clone().with_owner(tx.to_address().clone()))
+                    }
+                    _ => None,
+                };
+                (id.clone(), nft)
+            }
+            _ => return Err(BlockValidationError::InvalidInput),
+        };
+
+        self.set_value(from_address, tx.fee(), true)?;
+        self.write(StateKey::Nft(id), nft.map(StateValue::Nft));
+        self.set_value(miner, tx.fee(), false)
+    }
+
+    // create the token of an issue transaction and give its supply to the issuer.
+    fn issue(&mut self, tx: &Transaction, miner: &Hash) -> Result<(), BlockValidationError> {
+        let token = Token::from_issue(tx)?;
+
+        // the nonce keeps an issue from being applied twice, the token list checks anyway.
+        let asset = asset_id(&tx.id());
+        if self.tokens.contains_key(&asset) {
+            return Err(BlockValidationError::InvalidToken);
+        }
+
+        self.set_value(tx.from_address(), tx.fee(), true)?;
+        self.set_asset_value(tx.from_address(), &asset, token.supply(), false)?;
+        self.write(StateKey::Token(asset), Some(StateValue::Token(token)));
+        self.set_value(miner, tx.fee(), false)
+    }
+
+    // every spend has to be signed by the sender and carry its next nonce. only the genesis
+    // allocations and the miner reward move value without a signature.
+    fn authorize(
+        &mut self,
+        tx: &Transaction,
+        context: &SpendContext,
+        check_signatures: bool,
+    ) -> Result<(), BlockValidationError> {
+        if !tx.is_signed() {
+            return Err(BlockValidationError::UnsignedTransaction);
+        }
+
+        if check_signatures {
+            tx.check_signatures()?;
+            tx.check_script(context.height, context.timestamp, context.max_script_steps)?;
+        }
+
+        let from_address = tx.from_address();
+        let expected = self.nonce(from_address);
+        if tx.nonce() != expected {
+            return Err(BlockValidationError::MismatchedNonce {
+                expected,
+                found: tx.nonce(),
+            });
+        }
+        self.write(
+            StateKey::Nonce(from_address.clone()),
+            Some(StateValue::Word(expected + 1)),
+        );
+
+        Ok(())
+    }
+
+    fn run_contract(
+        &mut self,
+        tx: &Transaction,
+        miner: &Hash,
+        params: &ChainParams,
+        spend: &SpendContext,
+        check_signatures: bool,
+    ) -> Result<Receipt, BlockValidationError> {
+        let from_address = tx.from_address();
+        if !tx.is_native() {
+            return Err(BlockValidationError::InvalidToken);
+        }
+
+        self.authorize(tx, spend, check_signatures)?;
+        self.set_value(from_address, tx.fee(), true)?;
+
+        // the data is paid for first, the rest of the fee buys gas.
+        let data_fee = params.data_fee(tx.data().len()).min(tx.fee());
+        let gas_limit = (tx.fee() - data_fee)
+            .checked_div(params.gas_price())
+            .unwrap_or(u64::MAX)
+            .min(params.max_tx_gas());
+
+        let mark = self.journal_len();
+        let receipt = match tx.kind() {
+            TransactionKind::Deploy(program) => {
+                let context = CallContext::new(
+                    contract_address(&tx.id()),
+                    from_address.clone(),
+                    *tx.value(),
+                    vec![],
+                    spend.height(),
+                );
+                vm::deploy(tx.id(), &context, program, self, gas_limit)
+            }
+            TransactionKind::Call(args) => {
+                let context = CallContext::new(
+                    tx.to_address().clone(),
+                    from_address.clone(),
+                    *tx.value(),
+                    args.clone(),
+                    spend.height(),
+                );
+                vm::call(tx.id(), &context, self, gas_limit)
+            }
+            _ => return Err(BlockValidationError::InvalidInput),
+        };
+        if !receipt.is_success() {
+            self.rollback_to(mark);
+        }
+
+        let charged = receipt
+            .gas_used()
+            .saturating_mul(params.gas_price())
+            .saturating_add(data_fee)
+            .min(tx.fee());
+        self.set_value(miner, charged, false)?;
+        self.set_value(from_address, tx.fee() - charged, false)?;
+
+        Ok(receipt)
+    }
+
+    fn get(&self, key: &StateKey) -> Option<StateValue> {
+        match key {
+            StateKey::Balance(address, asset) => self
+                .values_store
+                .get(&(address.clone(), asset.clone()))
+                .copied()
+                .map(StateValue::Word),
+            StateKey::Nonce(address) => self.nonces.get(address).copied().map(StateValue::Word),
+            StateKey::Token(asset) => self.tokens.get(asset).cloned().map(StateValue::Token),
+            StateKey::Nft(nft) => self.nfts.get(nft).cloned().map(StateValue::Nft),
+            StateKey::Code(address) => self.code.get(address).cloned().map(StateValue::Code),
+            StateKey::Storage(address, slot) => self
+                .storage
+                .get(&(address.clone(), *slot))
+                .copied()
+                .map(StateValue::Word),
+        }
+    }
+
+    // change a value without journaling it.
+    fn put(&mut self, key: &StateKey, value: Option<StateValue>) {
+        let bytes = value.as_ref().map(StateValue::to_bytes);
+        self.tree.update(&key.to_bytes(), bytes.as_deref());
+
+        match key {
+            StateKey::Balance(address, asset) => put_map(
+                &mut self.values_store,
+                (address.clone(), asset.clone()),
+                value.and_then(StateValue::word),
+            ),
+            StateKey::Token(asset) => put_map(
+                &mut self.tokens,
+                asset.clone(),
+                value.and_then(StateValue::token),
+            ),
+            StateKey::Nft(nft) => {
+                put_map(&mut self.nfts, nft.clone(), value.and_then(StateValue::nft))
+            }
+            StateKey::Nonce(address) => put_map(
+                &mut self.nonces,
+                address.clone(),
+                value.and_then(StateValue::word),
+            ),
+            StateKey::Code(address) => put_map(
+                &mut self.code,
+                address.clone(),
+                value.and_then(StateValue::code),
+            ),
+            StateKey::Storage(address, slot) => put_map(
+                &mut self.storage,
+                (address.clone(), *slot),
+                value.and_then(StateValue::word),
+            ),
+        }
+    }
+
+    fn write(&mut self, key: StateKey, value: Option<StateValue>) {
+        let previous = self.get(&key);
+        self.put(&key, value);
+        self.journal.push((key, previous));
+    }
+
+    fn write_balance(&mut self, address: &Hash, value: Option<u64>) {
+        self.write_asset_balance(address, NATIVE_ASSET, value);
+    }
+
+    fn write_asset_balance(&mut self, address: &Hash, asset: &str, value: Option<u64>) {
+        self.write(
+            StateKey::Balance(address.clone(), asset.to_string()),
+            value.map(StateValue::Word),
+        );
     }
 
     fn set_value(
@@ -66,34 +746,529 @@ impl ValueStore {
         key: &Hash,
         value: u64,
         is_sender: bool,
+    ) -> Result<(), BlockValidationError> {
+        self.set_asset_value(key, NATIVE_ASSET, value, is_sender)
+    }
+
+    fn set_asset_value(
+        &mut self,
+        key: &Hash,
+        asset: &str,
+        value: u64,
+        is_sender: bool,
     ) -> Result<(), BlockValidationError> {
         if value == 0 {
             return Ok(());
         }
 
-        let value_wrapped = self.values_store.get_mut(key);
+        match self.asset_balance(key, asset) {
+            Some(target_value) => {
+                // in case sender, we deduct value from the asset.
+                if is_sender {
+                    if target_value < value {
+                        return Err(BlockValidationError::InsufficientInputVal);
+                    }
 
-        if let Some(target_value) = value_wrapped {
-            // in case sender, we deduct value from the asset.
-            if is_sender {
-                if *target_value < value {
-                    return Err(BlockValidationError::InsufficientInputVal);
+                    self.write_asset_balance(key, asset, Some(target_value - value));
+                } else {
+                    let target_value = target_value
+                        .checked_add(value)
+                        .ok_or(BlockValidationError::InvalidInput)?;
+                    self.write_asset_balance(key, asset, Some(target_value));
                 }
-
-                *target_value = *target_value - value;
-                return Ok(());
-            } else {
-                *target_value = *target_value + value;
-                return Ok(());
+                Ok(())
             }
-        } else {
-            // in case of no wallet 
-            if is_sender {
-                return Err(BlockValidationError::InvalidInput);
+            None => {
+                // in case of no wallet
+                if is_sender {
+                    return Err(BlockValidationError::InvalidInput);
+                }
+
+                self.write_asset_balance(key, asset, Some(value));
+                Ok(())
             }
+        }
+    }
+}
 
-            self.values_store.insert(key.clone(), value);
-            return Ok(());
+impl ContractState for ValueStore {
+    fn code(&self, contract: &Hash) -> Option<Program> {
+        self.contract_code(contract).cloned()
+    }
+
+    fn set_code(&mut self, contract: &Hash, program: Program) {
+        self.write(
+            StateKey::Code(contract.clone()),
+            Some(StateValue::Code(program)),
+        );
+    }
+
+    fn load(&self, contract: &Hash, slot: u64) -> u64 {
+        self.storage(contract, slot)
+    }
+
+    fn store(&mut self, contract: &Hash, slot: u64, word: u64) {
+        let value = match word {
+            0 => None,
+            word => Some(StateValue::Word(word)),
+        };
+        self.write(StateKey::Storage(contract.clone(), slot), value);
+    }
+
+    fn balance_of(&self, address: &Hash) -> u64 {
+        self.balance(address).unwrap_or(0)
+    }
+
+    fn pay(&mut self, from: &Hash, to: &Hash, amount: u64) -> bool {
+        // a failing debit changes nothing, and crediting can't fail.
+        self.set_value(from, amount, true).is_ok() && self.set_value(to, amount, false).is_ok()
+    }
+}
+
+fn put_map<K: std::hash::Hash + Eq, V>(map: &mut HashMap<K, V>, key: K, value: Option<V>) {
+    match value {
+        Some(value) => map.insert(key, value),
+        None => map.remove(&key),
+    };
+}
+
+// full copy of the state, tagged with the block it was taken at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateSnapshot {
+    height: u64,
+    block_hash: Hash,
+    entries: Vec<StateEntry>,
+}
+
+impl StateSnapshot {
+    pub fn new(height: u64, block_hash: Hash, entries: Vec<StateEntry>) -> Self {
+        StateSnapshot {
+            height,
+            block_hash,
+            entries,
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn block_hash(&self) -> &Hash {
+        &self.block_hash
+    }
+
+    pub fn entries(&self) -> &Vec<StateEntry> {
+        &self.entries
+    }
+}
+
+impl Encodable for StateSnapshot {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        bytes.extend(self.height.to_le_bytes());
+        put_str(&mut bytes, &self.block_hash);
+        bytes.extend((self.entries.len() as u32).to_le_bytes());
+
+        for (key, value) in self.entries.iter() {
+            put_bytes(&mut bytes, key);
+            put_bytes(&mut bytes, value);
+        }
+
+        bytes
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError> {
+        let height = reader.u64()?;
+        let block_hash = reader.string()?;
+        let count = reader.u32()?;
+
+        let mut entries = vec![];
+        for _ in 0..count {
+            entries.push((reader.bytes()?, reader.bytes()?));
+        }
+
+        Ok(StateSnapshot {
+            height,
+            block_hash,
+            entries,
+        })
+    }
+}
+
+fn parse_key(key: &[u8]) -> Result<StateKey, DecodeError> {
+    let address =
+        |bytes: &[u8]| String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidData);
+
+    if let Some(rest) = key.strip_prefix(BALANCE_PREFIX) {
+        return Ok(StateKey::Balance(address(rest)?, NATIVE_ASSET.to_string()));
+    }
+    if let Some(rest) = key.strip_prefix(ASSET_PREFIX) {
+        // asset ids are hex, so the first '/' ends it.
+        let split = rest
+            .iter()
+            .position(|byte| *byte == b'/')
+            .ok_or(DecodeError::InvalidData)?;
+        return Ok(StateKey::Balance(
+            address(&rest[split + 1..])?,
+            address(&rest[..split])?,
+        ));
+    }
+    if let Some(rest) = key.strip_prefix(NONCE_PREFIX) {
+        return Ok(StateKey::Nonce(address(rest)?));
+    }
+    if let Some(rest) = key.strip_prefix(TOKEN_PREFIX) {
+        return Ok(StateKey::Token(address(rest)?));
+    }
+    if let Some(rest) = key.strip_prefix(NFT_PREFIX) {
+        return Ok(StateKey::Nft(address(rest)?));
+    }
+    if let Some(rest) = key.strip_prefix(CODE_PREFIX) {
+        return Ok(StateKey::Code(address(rest)?));
+    }
+    if let Some(rest) = key.strip_prefix(STORAGE_PREFIX) {
+        // the slot is the last 8 bytes, after a '/'.
+        let split = rest.len().checked_sub(9).ok_or(DecodeError::InvalidData)?;
+        if rest[split] != b'/' {
+            return Err(DecodeError::InvalidData);
         }
+
+        let mut slot = [0_u8; 8];
+        slot.copy_from_slice(&rest[split + 1..]);
+        return Ok(StateKey::Storage(
+            address(&rest[..split])?,
+            u64::from_be_bytes(slot),
+        ));
     }
+
+    Err(DecodeError::InvalidData)
+}
+
+fn parse_entry(key: &[u8], value: &[u8]) -> Result<(StateKey, StateValue), DecodeError> {
+    let key = parse_key(key)?;
+
+    match key {
+        StateKey::Token(_) => return Ok((key, StateValue::Token(Token::from_bytes(value)?))),
+        StateKey::Nft(_) => return Ok((key, StateValue::Nft(Nft::from_bytes(value)?))),
+        StateKey::Code(_) => return Ok((key, StateValue::Code(Program::from_bytes(value)?))),
+        _ => {}
+    }
+
+    let mut arr = [0_u8; 8];
+    if value.len() != arr.len() {
+        return Err(DecodeError::InvalidData);
+    }
+    arr.copy_from_slice(value);
+
+    Ok((key, StateValue::Word(u64::from_le_bytes(arr))))
+}
+
+#[test]
+fn test_rollback_and_commit() {
+    let mut store = ValueStore::new();
+    let alice = "alice".to_string();
+    let bob = "bob".to_string();
+
+    store.write_balance(&alice, Some(10));
+    assert_eq!(store.commit().len(), 1);
+
+    store.set_transaction(&alice, &bob, 4).unwrap();
+    store.rollback();
+    assert_eq!(store.balance(&alice), Some(10));
+    assert_eq!(store.balance(&bob), None);
+
+    store.set_transaction(&alice, &bob, 4).unwrap();
+    let writes = store.commit();
+    assert_eq!(writes.len(), 2);
+
+    let entries = writes
+        .into_iter()
+        .map(|(key, value)| (key, value.unwrap()))
+        .collect();
+    let restored = ValueStore::from_state(entries).unwrap();
+    assert_eq!(restored.balance(&bob), Some(4));
+    assert_eq!(restored.balance(&alice), Some(6));
+
+    let mark = store.journal_len();
+    store
+        .set_transaction(&bob, &"carol".to_string(), 4)
+        .unwrap();
+    let undo = store.undo_since(mark);
+    store.commit();
+
+    store.apply_writes(&undo).unwrap();
+    store.commit();
+    assert_eq!(store.balance(&bob), Some(4));
+    assert_eq!(store.balance(&"carol".to_string()), None);
+}
+
+#[test]
+fn test_snapshot_roundtrip() {
+    let mut store = ValueStore::new();
+    store.write_balance(&"alice".to_string(), Some(10));
+    store.write_balance(&"bob".to_string(), Some(3));
+    store.commit();
+
+    let snapshot = store.export_snapshot(4, "block-hash".to_string());
+    let decoded = StateSnapshot::from_bytes(&snapshot.encode()).unwrap();
+    assert_eq!(decoded, snapshot);
+    assert_eq!(decoded.height(), 4);
+
+    let restored = ValueStore::import_snapshot(&decoded).unwrap();
+    assert_eq!(restored.state_entries(), store.state_entries());
+}
+
+#[test]
+fn test_batch_is_atomic() {
+    use crate::backend::functions::address_of;
+    use crypto::ed25519;
+
+    let (secret_key, public_key) = ed25519::keypair(&[1; 32]);
+    let mut store = ValueStore::new();
+    let alice = address_of(&public_key);
+    let miner = "miner".to_string();
+    let context = SpendContext::new(1, 0, &ChainParams::regtest());
+    store.write_balance(&alice, Some(100));
+    store.commit();
+
+    let payroll = Transaction::batch(
+        alice.clone(),
+        vec![("bob".to_string(), 30), ("carol".to_string(), 40)],
+    )
+    .with_fee(2)
+    .sign(&secret_key);
+    store.apply_transaction(&payroll, &miner, &context).unwrap();
+    assert_eq!(store.balance(&alice), Some(28));
+    assert_eq!(store.balance(&"carol".to_string()), Some(40));
+    assert_eq!(store.balance(&miner), Some(2));
+    store.commit();
+
+    // the sender can't cover every payout, so nobody gets paid and the nonce stays.
+    let overdrawn = Transaction::batch(
+        alice.clone(),
+        vec![("dave".to_string(), 10), ("erin".to_string(), 20)],
+    )
+    .with_nonce(1)
+    .sign(&secret_key);
+    assert!(store
+        .apply_transaction(&overdrawn, &miner, &context)
+        .is_err());
+    assert_eq!(store.balance(&"dave".to_string()), None);
+    assert_eq!(store.balance(&alice), Some(28));
+    assert_eq!(store.nonce(&alice), 1);
+    assert!(store.commit().is_empty());
+
+    let empty = Transaction::batch(alice, vec![])
+        .with_nonce(1)
+        .sign(&secret_key);
+    assert!(matches!(
+        store.apply_transaction(&empty, &miner, &context),
+        Err(BlockValidationError::InvalidBatch)
+    ));
+}
+
+#[test]
+fn test_token_issue_and_transfer() {
+    use super::asset::asset_id;
+    use crate::backend::functions::address_of;
+    use crypto::ed25519;
+
+    let (alice_key, alice_public) = ed25519::keypair(&[1; 32]);
+    let (bob_key, bob_public) = ed25519::keypair(&[2; 32]);
+    let mut store = ValueStore::new();
+    let alice = address_of(&alice_public);
+    let bob = address_of(&bob_public);
+    let miner = "miner".to_string();
+    let context = SpendContext::new(1, 0, &ChainParams::regtest());
+    store.write_balance(&alice, Some(10));
+    store.commit();
+
+    let bad =
+        Transaction::issue(alice.clone(), "NOT A SYMBOL".to_string(), 2, 1_000).sign(&alice_key);
+    assert!(matches!(
+        store.apply_transaction(&bad, &miner, &context),
+        Err(BlockValidationError::InvalidToken)
+    ));
+
+    let issue = Transaction::issue(alice.clone(), "GOLD".to_string(), 2, 1_000)
+        .with_fee(1)
+        .sign(&alice_key);
+    store.apply_transaction(&issue, &miner, &context).unwrap();
+    store.commit();
+
+    let gold = asset_id(&issue.id());
+    assert_eq!(store.token(&gold).unwrap().issuer(), &alice);
+    assert_eq!(store.asset_balance(&alice, &gold), Some(1_000));
+
+    // the value moves in gold, the fee in the native coin.
+    let transfer = Transaction::new(alice.clone(), bob.clone(), 300)
+        .with_asset(gold.clone())
+        .with_fee(1)
+        .with_nonce(1)
+        .sign(&alice_key);
+    store
+        .apply_transaction(&transfer, &miner, &context)
+        .unwrap();
+    store.commit();
+    assert_eq!(store.asset_balance(&bob, &gold), Some(300));
+    assert_eq!(store.asset_balance(&alice, &gold), Some(700));
+    assert_eq!(store.balance(&alice), Some(8));
+    assert_eq!(store.balance(&bob), None);
+    assert_eq!(store.balance(&miner), Some(2));
+
+    let overdrawn = Transaction::new(bob.clone(), alice.clone(), 301)
+        .with_asset(gold.clone())
+        .sign(&bob_key);
+    assert!(matches!(
+        store.apply_transaction(&overdrawn, &miner, &context),
+        Err(BlockValidationError::InsufficientInputVal)
+    ));
+
+    // only holders move gold, and only with their own key.
+    let (carol_key, carol_public) = ed25519::keypair(&[3; 32]);
+    let carol = address_of(&carol_public);
+    let empty_handed = Transaction::new(carol.clone(), bob.clone(), 1)
+        .with_asset(gold.clone())
+        .sign(&carol_key);
+    assert!(matches!(
+        store.apply_transaction(&empty_handed, &miner, &context),
+        Err(BlockValidationError::InvalidInput)
+    ));
+    let taken = Transaction::new(alice.clone(), carol.clone(), 100)
+        .with_asset(gold.clone())
+        .with_nonce(2);
+    assert!(matches!(
+        store.apply_transaction(&taken, &miner, &context),
+        Err(BlockValidationError::UnsignedTransaction)
+    ));
+    assert!(matches!(
+        store.apply_transaction(&taken.sign(&carol_key), &miner, &context),
+        Err(BlockValidationError::InvalidSignature)
+    ));
+    assert_eq!(store.asset_balance(&carol, &gold), None);
+
+    let unknown = Transaction::new(alice.clone(), bob.clone(), 1)
+        .with_asset("silver".to_string())
+        .with_nonce(2)
+        .sign(&alice_key);
+    assert!(matches!(
+        store.apply_transaction(&unknown, &miner, &context),
+        Err(BlockValidationError::InvalidToken)
+    ));
+
+    // tokens and asset balances are part of the state.
+    let restored = ValueStore::from_state(store.state_entries()).unwrap();
+    assert_eq!(restored.commitment(), store.commitment());
+    assert_eq!(restored.token(&gold), store.token(&gold));
+    assert_eq!(restored.asset_balance(&bob, &gold), Some(300));
+
+    // a balance which would overflow takes nothing more.
+    store.write_asset_balance(&carol, &gold, Some(u64::MAX));
+    let overflowing = Transaction::new(alice.clone(), carol.clone(), 1)
+        .with_asset(gold.clone())
+        .with_nonce(2)
+        .sign(&alice_key);
+    assert!(matches!(
+        store.apply_transaction(&overflowing, &miner, &context),
+        Err(BlockValidationError::InvalidInput)
+    ));
+    assert_eq!(store.asset_balance(&alice, &gold), Some(700));
+}
+
+#[test]
+fn test_nft_registry() {
+    use super::nft::nft_id;
+    use crate::backend::functions::address_of;
+    use crypto::ed25519;
+
+    let (alice_key, alice_public) = ed25519::keypair(&[1; 32]);
+    let (bob_key, bob_public) = ed25519::keypair(&[2; 32]);
+    let mut store = ValueStore::new();
+    let alice = address_of(&alice_public);
+    let bob = address_of(&bob_public);
+    let miner = "miner".to_string();
+    let context = SpendContext::new(1, 0, &ChainParams::regtest());
+    store.write_balance(&alice, Some(10));
+    store.write_balance(&bob, Some(10));
+    store.commit();
+
+    let mint = Transaction::mint(alice.clone(), alice.clone(), "metadata-hash".to_string())
+        .with_fee(1)
+        .sign(&alice_key);
+    store.apply_transaction(&mint, &miner, &context).unwrap();
+    store.commit();
+
+    let id = nft_id(&mint.id());
+    assert_eq!(store.nft(&id).unwrap().owner(), &alice);
+    assert_eq!(store.nft(&id).unwrap().metadata(), "metadata-hash");
+    assert_eq!(store.nfts_of(&alice), vec![&id]);
+    // the nonce keeps the same token from being minted twice.
+    assert!(matches!(
+        store.apply_transaction(&mint, &miner, &context),
+        Err(BlockValidationError::MismatchedNonce { .. })
+    ));
+
+    // bob can't take what isn't his.
+    let stolen = Transaction::transfer_nft(bob.clone(), id.clone(), bob.clone()).sign(&bob_key);
+    assert!(matches!(
+        store.apply_transaction(&stolen, &miner, &context),
+        Err(BlockValidationError::NotNftOwner)
+    ));
+
+    // naming the owner as sender isn't enough, the owner has to sign.
+    let unsigned = Transaction::transfer_nft(alice.clone(), id.clone(), bob.clone()).with_nonce(1);
+    assert!(matches!(
+        store.apply_transaction(&unsigned, &miner, &context),
+        Err(BlockValidationError::UnsignedTransaction)
+    ));
+    assert!(matches!(
+        store.apply_transaction(
+            &Transaction::burn(alice.clone(), id.clone()),
+            &miner,
+            &context
+        ),
+        Err(BlockValidationError::UnsignedTransaction)
+    ));
+    assert!(matches!(
+        store.apply_transaction(&unsigned.sign(&bob_key), &miner, &context),
+        Err(BlockValidationError::InvalidSignature)
+    ));
+    assert_eq!(store.nft(&id).unwrap().owner(), &alice);
+
+    let gift = Transaction::transfer_nft(alice.clone(), id.clone(), bob.clone())
+        .with_fee(1)
+        .with_nonce(1)
+        .sign(&alice_key);
+    store.apply_transaction(&gift, &miner, &context).unwrap();
+    store.commit();
+    assert_eq!(store.nfts_of(&bob), vec![&id]);
+    assert!(store.nfts_of(&alice).is_empty());
+    assert_eq!(store.balance(&alice), Some(8));
+    assert_eq!(store.balance(&miner), Some(2));
+
+    // the registry is part of the state commitment.
+    let restored = ValueStore::from_state(store.state_entries()).unwrap();
+    assert_eq!(restored.commitment(), store.commitment());
+    assert_eq!(restored.nft(&id), store.nft(&id));
+
+    let committed = store.commitment();
+    let burn = Transaction::burn(alice.clone(), id.clone())
+        .with_nonce(2)
+        .sign(&alice_key);
+    assert!(matches!(
+        store.apply_transaction(&burn, &miner, &context),
+        Err(BlockValidationError::NotNftOwner)
+    ));
+    let burn = Transaction::burn(bob.clone(), id.clone()).sign(&bob_key);
+    store.apply_transaction(&burn, &miner, &context).unwrap();
+    store.commit();
+    assert_eq!(store.nft(&id), None);
+    assert_ne!(store.commitment(), committed);
+    let gone = Transaction::transfer_nft(bob, id, alice)
+        .with_nonce(1)
+        .sign(&bob_key);
+    assert!(matches!(
+        store.apply_transaction(&gone, &miner, &context),
+        Err(BlockValidationError::UnknownNft)
+    ));
 }
@@ -1,2 +1,11 @@
 pub type Address = String;
-pub type Hash = String;
\ No newline at end of file
+pub type Hash = String;
+// `NATIVE_ASSET`, or the id of an issued token.
+pub type AssetId = String;
+// `nft_id` of the transaction which minted the token.
+pub type NftId = String;
+
+// raw key / value pair of the account state.
+pub type StateEntry = (Vec<u8>, Vec<u8>);
+// a state key with its new value, `None` when it is removed.
+pub type StateWrite = (Vec<u8>, Option<Vec<u8>>);
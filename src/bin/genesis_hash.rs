@@ -0,0 +1,38 @@
+use rust_blockchain_seman::backend::{ChainParams, GenesisSpec};
+
+// prints the hash of the genesis block described by a spec file.
+// usage: genesis_hash <spec.json> [mainnet|testnet|regtest]
+fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+
+    let path = match args.get(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: genesis_hash <spec.json> [mainnet|testnet|regtest]");
+            std::process::exit(2);
+        }
+    };
+
+    let base = match args.get(2).map(|network| network.as_str()) {
+        None | Some("mainnet") => ChainParams::mainnet(),
+        Some("testnet") => ChainParams::testnet(),
+        Some("regtest") => ChainParams::regtest(),
+        Some(other) => {
+            eprintln!("unknown network {}", other);
+            std::process::exit(2);
+        }
+    };
+
+    let result = GenesisSpec::from_file(path).and_then(|spec| {
+        let params = spec.params(base)?;
+        spec.build(&params)
+    });
+
+    match result {
+        Ok(genesis) => println!("{}", genesis.current_hash()),
+        Err(e) => {
+            eprintln!("invalid genesis spec - {:?}", e);
+            std::process::exit(1);
+        }
+    }
+}
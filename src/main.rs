@@ -1,50 +1,65 @@
-use crypto::{digest::Digest, sha2::Sha256};
+use crypto::{digest::Digest, ed25519, sha2::Sha256};
 use rust_blockchain_seman::backend::{
-    get_genesis_hasher, get_hash, Block, BlockChain, Transaction, Transactions,
+    address_of, get_hash, hash_str_bytes, Block, BlockChain, BlockTemplate, ChainParams, Clock,
+    Mempool, SystemClock, Transaction, Transactions,
 };
 
 fn main() {
     test_process();
     // test sucessful!
-    loop {}
+    loop {
+        std::thread::park();
+    }
 }
 
 fn test_process() {
+    let params = ChainParams::mainnet();
+
+    // Bob signs his transactions, so his wallet comes from a key.
+    let (bob_key, bob_public) = ed25519::keypair(&hash_str_bytes(&get_hash("Bob".to_string())));
+    let test_wallet_1 = address_of(&bob_public);
+    let test_wallet_2 = get_hash("Tom".to_string());
+    let test_wallet_3 = get_hash("John".to_string());
+
     // genesis block
-    let genesis_block = Block::gen_genesis();
-    // println!("{}", genesis_block.timestamp());
-    let mut block_chain = BlockChain::new();
+    let mut allocations = Transactions::empty();
+    allocations.values_mut().push(Transaction::new(
+        params.coinbase_input(""),
+        test_wallet_1.clone(),
+        params.genesis_balance(),
+    ));
+    let genesis_block = Block::mine_genesis(
+        allocations,
+        test_wallet_1,
+        params.difficulty(),
+        SystemClock.now(),
+    );
+    let mut block_chain = BlockChain::with_params(params);
 
     _ = block_chain.update_block(genesis_block);
 
     let mut broadcaster_hasher = Sha256::new();
     broadcaster_hasher.input_str("broadcaster");
 
-    let _broadcaster = broadcaster_hasher.result_str();
+    let mut mempool = Mempool::default();
 
-    let mut transactions = Transactions::empty();
+    let tx1 = Transaction::signed(&bob_key, test_wallet_2, 50, 0, 1);
+    let tx2 = Transaction::signed(&bob_key, test_wallet_3, 50, 1, 1);
 
-    let test_wallet_1 = get_hash("Bob".to_string());
-    let test_wallet_2 = get_hash("Tom".to_string());
-    let test_wallet_3 = get_hash("John".to_string());
-
-    let tx1 = Transaction::new(get_genesis_hasher(), test_wallet_1.clone(), 50);
-    let tx2 = Transaction::new(test_wallet_1.clone(), test_wallet_2.clone(), 50);
-    let tx3 = Transaction::new(get_genesis_hasher(), test_wallet_3, 50);
+    for tx in [tx1, tx2] {
+        if let Err(e) = mempool.add(tx, block_chain.value_store()) {
+            println!("{:?}", e);
+        }
+    }
 
-    transactions.values_mut().push(tx1);
-    transactions.values_mut().push(tx2);
-    transactions.values_mut().push(tx3);
-
-    let new_block = Block::mine(
-        block_chain.get_block_size() as u64 + 1,
-        transactions,
-        broadcaster_hasher.result_str(),
-    );
+    let result = BlockTemplate::new(&mut block_chain, &mempool, broadcaster_hasher.result_str())
+        .and_then(|template| block_chain.update_block(template.mine()));
 
-    let err = block_chain.update_block(new_block);
+    if let Ok(update) = &result {
+        mempool.update(update, block_chain.value_store());
+    }
 
-    println!("{:?}", err);
+    println!("{:?}", result.map(|update| update.connected().len()));
     println!("{:?}", block_chain.get_block_size());
     println!("{:?}", block_chain.value_store());
 }